@@ -7,23 +7,65 @@ use std::{
 const SHADER_DIR: &str = "shaders-generated";
 const INCLUDE_PREFIX: &str = "///#include";
 
+// An included file and the one it is replaced with
+type Replacement<'a> = (&'a str, &'a str);
+
 fn main() -> Result<(), Box<dyn Error>> {
     println!("cargo:rerun-if-changed=shaders/");
 
-    let shader_files = ["render.wgsl", "post_process.wgsl"];
+    let shader_files = [
+        "render.wgsl",
+        "resolve.wgsl",
+        "exposure.wgsl",
+        "tone_map.wgsl",
+        "lut.wgsl",
+        "vignette.wgsl",
+        "dither.wgsl",
+        "quantize.wgsl",
+        "quantize_float.wgsl",
+        "histogram.wgsl",
+        "denoise.wgsl",
+        "bloom.wgsl",
+    ];
+
+    // Variants of the render shader with includes replaced, for adapters lacking a feature or a
+    // limit and for the compact layout of the geometry. Each combination of the replacements is
+    // named by their suffixes.
+    let render_replacements: [(&str, Replacement); 3] = [
+        ("_atlas", ("textures.wgsl", "textures_atlas.wgsl")),
+        ("_compact", ("core/geometry.wgsl", "core/geometry_compact.wgsl")),
+        ("_no_aovs", ("aov.wgsl", "aov_disabled.wgsl")),
+    ];
 
     // Path prefix is not necessary
     fs::create_dir_all(SHADER_DIR)?;
     for file in shader_files {
-        generate_shader(file)?;
+        generate_shader(file, file, &[])?;
+    }
+    for variant in 1..1 << render_replacements.len() {
+        let (suffixes, replacements): (String, Vec<_>) = render_replacements
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| variant & 1 << i != 0)
+            .map(|(_, replacement)| *replacement)
+            .unzip();
+        generate_shader(
+            "render.wgsl",
+            &format!("render{suffixes}.wgsl"),
+            &replacements,
+        )?;
     }
 
     Ok(())
 }
 
-fn generate_shader(file_name: &str) -> Result<(), Box<dyn Error>> {
+fn generate_shader(
+    file_name: &str,
+    out_file_name: &str,
+    replacements: &[Replacement],
+) -> Result<(), Box<dyn Error>> {
     let path = format!("shaders/{}", file_name);
-    let out_path = SHADER_DIR.to_string() + &format!("/{}", file_name);
+    let out_path = SHADER_DIR.to_string() + &format!("/{}", out_file_name);
 
     let source = match fs::read_to_string(&path) {
         Ok(source) => source,
@@ -31,7 +73,7 @@ fn generate_shader(file_name: &str) -> Result<(), Box<dyn Error>> {
     };
 
     let mut result = String::new();
-    preprocess_shader(&source, &mut result);
+    preprocess_shader(&source, replacements, &mut result);
 
     let mut file = File::create(out_path)?;
     file.write_all(result.as_bytes())?;
@@ -39,12 +81,15 @@ fn generate_shader(file_name: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn preprocess_shader(source: &str, result: &mut String) {
+fn preprocess_shader(source: &str, replacements: &[Replacement], result: &mut String) {
     for line in source.lines() {
         if let Some(stripped) = line.strip_prefix(INCLUDE_PREFIX) {
-            let include_file = stripped.trim().replace('"', "");
+            let mut include_file = stripped.trim().replace('"', "");
+            if let Some((_, to)) = replacements.iter().find(|(from, _)| *from == include_file) {
+                include_file = to.to_string();
+            }
             let include_source = get_include_source(&include_file);
-            preprocess_shader(&include_source, result);
+            preprocess_shader(&include_source, replacements, result);
         } else {
             result.push_str(line);
             result.push('\n');
@@ -0,0 +1,39 @@
+// Enabled only if any of the AOVs is requested
+override AOVS: bool = false;
+
+@group(0) @binding(1)
+var albedo_target: texture_storage_2d<rgba32float, read_write>;
+
+@group(0) @binding(2)
+var normal_target: texture_storage_2d<rgba32float, read_write>;
+
+@group(0) @binding(3)
+var position_target: texture_storage_2d<rgba32float, read_write>;
+
+@group(0) @binding(4)
+var id_target: texture_storage_2d<rgba32uint, write>;
+
+// The first hit of a camera ray, which is zero if the ray escapes
+struct Aov {
+    albedo: vec3f,
+    normal: vec3f,
+    position: vec3f,
+    depth: f32,
+    // The indices are offset by one so that zero stands for the background
+    primitive: u32,
+    material: u32,
+}
+
+// The number of samples, the depth and the second moment of the demodulated luminance for the
+// denoiser are accumulated in the alpha channels, while the IDs are those of the latest sample
+// since they cannot be averaged
+fn aov_store(id: vec2u, aov: Aov, color: vec3f) {
+    let albedo = textureLoad(albedo_target, id);
+    textureStore(albedo_target, id, albedo + vec4(aov.albedo, 1.0));
+    let normal = textureLoad(normal_target, id);
+    textureStore(normal_target, id, normal + vec4(aov.normal, aov.depth));
+    let position = textureLoad(position_target, id);
+    let illumination = luminance(color / demodulation(aov.albedo));
+    textureStore(position_target, id, position + vec4(aov.position, illumination * illumination));
+    textureStore(id_target, id, vec4(aov.primitive, aov.material, 0u, 0u));
+}
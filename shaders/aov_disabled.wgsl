@@ -0,0 +1,13 @@
+// Replaces the AOVs if none is requested, so that only the render target is bound
+override AOVS: bool = false;
+
+struct Aov {
+    albedo: vec3f,
+    normal: vec3f,
+    position: vec3f,
+    depth: f32,
+    primitive: u32,
+    material: u32,
+}
+
+fn aov_store(id: vec2u, aov: Aov, color: vec3f) {}
@@ -0,0 +1,172 @@
+///#include "utils/color.wgsl"
+///#include "utils/constants.wgsl"
+
+override THRESHOLD: f32;
+override INTENSITY: f32;
+override LEVELS: u32;
+override STREAKS: u32 = 0u;
+override STREAK_LENGTH: u32 = 0u;
+
+@group(0) @binding(0)
+var src_texture: texture_storage_2d<rgba32float, read>;
+
+// The coarser level when upsampling, and the bloom when compositing
+@group(0) @binding(1)
+var bloom_texture: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(2)
+var glare_texture: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(3)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+// The size of the texture being sampled, which is the coarser level when upsampling
+struct Constants {
+    src_size: vec2u,
+    dst_size: vec2u,
+}
+
+var<push_constant> constants: Constants;
+
+// Storage textures can't be passed to functions on every backend, so they are chosen by binding.
+// The textures are larger than the levels of smaller tiles, so the coordinates are clamped.
+fn load(binding: u32, size: vec2u, position: vec2i) -> vec3f {
+    let p = vec2u(clamp(position, vec2(0), vec2i(size) - 1));
+    switch binding {
+        case 1u: {
+            return textureLoad(bloom_texture, p).xyz;
+        }
+        case 2u: {
+            return textureLoad(glare_texture, p).xyz;
+        }
+        default: {
+            return textureLoad(src_texture, p).xyz;
+        }
+    }
+}
+
+// The position is in texels, with the centers of the texels at half-integers
+fn sample_bilinear(binding: u32, size: vec2u, position: vec2f) -> vec3f {
+    let p = position - 0.5;
+    let base = vec2i(floor(p));
+    let t = p - floor(p);
+    let a = load(binding, size, base);
+    let b = load(binding, size, base + vec2(1, 0));
+    let c = load(binding, size, base + vec2(0, 1));
+    let d = load(binding, size, base + vec2(1, 1));
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+}
+
+// A quadratic knee eases the transition at the threshold
+fn threshold(color: vec3f) -> vec3f {
+    let knee = 0.5 * THRESHOLD;
+    let brightness = max(color.x, max(color.y, color.z));
+    var soft = clamp(brightness - THRESHOLD + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 1e-5);
+    return color * max(soft, brightness - THRESHOLD) / max(brightness, 1e-5);
+}
+
+// Halves the radiance above the threshold with a 4x4 tent filter
+@compute
+@workgroup_size(16, 16, 1)
+fn prefilter(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= constants.dst_size) {
+        return;
+    }
+
+    var kernel = array(1.0, 3.0, 3.0, 1.0);
+    var color = vec3(0.0);
+    for (var y = 0; y < 4; y++) {
+        for (var x = 0; x < 4; x++) {
+            let position = vec2i(id.xy) * 2 + vec2(x, y) - 1;
+            color += kernel[x] * kernel[y] * threshold(load(0u, constants.src_size, position));
+        }
+    }
+    textureStore(dst_texture, id.xy, vec4(color / 64.0, 1.0));
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn downsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= constants.dst_size) {
+        return;
+    }
+
+    var kernel = array(1.0, 3.0, 3.0, 1.0);
+    var color = vec3(0.0);
+    for (var y = 0; y < 4; y++) {
+        for (var x = 0; x < 4; x++) {
+            let position = vec2i(id.xy) * 2 + vec2(x, y) - 1;
+            color += kernel[x] * kernel[y] * load(0u, constants.src_size, position);
+        }
+    }
+    textureStore(dst_texture, id.xy, vec4(color / 64.0, 1.0));
+}
+
+// Adds the coarser level blurred by a 3x3 tent filter to the downsampled level
+@compute
+@workgroup_size(16, 16, 1)
+fn upsample(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= constants.dst_size) {
+        return;
+    }
+
+    var kernel = array(1.0, 2.0, 1.0);
+    let center = (vec2f(id.xy) + 0.5) * 0.5;
+    var color = vec3(0.0);
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let position = center + vec2f(vec2(x, y));
+            color += kernel[x + 1] * kernel[y + 1]
+                * sample_bilinear(1u, constants.src_size, position);
+        }
+    }
+    color = color / 16.0 + load(0u, constants.dst_size, vec2i(id.xy));
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
+
+// Smears the prefiltered radiance along evenly spaced streaks, which fade out with the distance
+@compute
+@workgroup_size(16, 16, 1)
+fn glare(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= constants.dst_size) {
+        return;
+    }
+
+    let center = vec2f(id.xy) + 0.5;
+    var color = vec3(0.0);
+    var weights = 0.0;
+    for (var i = 1u; i <= STREAK_LENGTH; i++) {
+        let falloff = 1.0 - f32(i) / f32(STREAK_LENGTH + 1u);
+        weights += falloff * falloff;
+    }
+    for (var streak = 0u; streak < STREAKS; streak++) {
+        // The streaks are turned away from the axes, which looks less artificial
+        let angle = 2.0 * PI * (f32(streak) + 0.25) / f32(STREAKS);
+        let direction = vec2(cos(angle), sin(angle));
+        for (var i = 1u; i <= STREAK_LENGTH; i++) {
+            let falloff = 1.0 - f32(i) / f32(STREAK_LENGTH + 1u);
+            let position = center - direction * f32(i);
+            color += falloff * falloff * sample_bilinear(0u, constants.src_size, position);
+        }
+    }
+    textureStore(dst_texture, id.xy, vec4(color / (weights * f32(STREAKS)), 1.0));
+}
+
+// Adds the bloom, averaged over the levels, and the glare to the radiance
+@compute
+@workgroup_size(16, 16, 1)
+fn composite(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= constants.dst_size) {
+        return;
+    }
+
+    let position = (vec2f(id.xy) + 0.5) * 0.5;
+    var bloom = sample_bilinear(1u, constants.src_size, position) / f32(LEVELS);
+    if STREAKS > 0u {
+        bloom += sample_bilinear(2u, constants.src_size, position);
+    }
+
+    let color = textureLoad(src_texture, id.xy).xyz + INTENSITY * bloom;
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
@@ -0,0 +1,37 @@
+override COLOR_SPACE: u32;
+
+// Converts linear Rec.709 colors to the primaries and transfer function of the output
+fn encode_color(color: vec3f) -> vec3f {
+    switch COLOR_SPACE {
+        case 1u: {
+            let rec709_to_p3 = mat3x3f(
+                0.8224621, 0.0331941, 0.0170827,
+                0.1775380, 0.9668058, 0.0723974,
+                0.0000000, 0.0000000, 0.9105199
+            );
+            return srgb_oetf(rec709_to_p3 * color);
+        }
+        case 2u: {
+            let rec709_to_rec2020 = mat3x3f(
+                0.6274040, 0.0690970, 0.0163916,
+                0.3292820, 0.9195400, 0.0880132,
+                0.0433136, 0.0113612, 0.8955950
+            );
+            return rec709_oetf(rec709_to_rec2020 * color);
+        }
+        default: {
+            return srgb_oetf(color);
+        }
+    }
+}
+
+fn srgb_oetf(color: vec3f) -> vec3f {
+    let c = clamp(color, vec3(0.0), vec3(1.0));
+    return select(1.055 * pow(c, vec3(1.0 / 2.4)) - 0.055, 12.92 * c, c <= vec3(0.0031308));
+}
+
+// Rec.2020 shares the transfer function of Rec.709
+fn rec709_oetf(color: vec3f) -> vec3f {
+    let c = clamp(color, vec3(0.0), vec3(1.0));
+    return select(1.099 * pow(c, vec3(0.45)) - 0.099, 4.5 * c, c < vec3(0.018));
+}
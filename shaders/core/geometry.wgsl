@@ -0,0 +1,81 @@
+// The vertices may be split into several bindings, which are read with `vertices(i)`
+///#chunked @group(2) @binding(0) vertices: array<Vertex>
+
+struct Vertex {
+    pos: vec3f,
+    normal: vec3f,
+    tex_coord: vec2f
+}
+
+fn vertex_pos(i: u32) -> vec3f {
+    return vertices(i).pos;
+}
+
+fn vertex_normal(i: u32) -> vec3f {
+    return vertices(i).normal;
+}
+
+fn vertex_tex_coord(i: u32) -> vec2f {
+    return vertices(i).tex_coord;
+}
+
+// The watertight test of the triangle, in the space where the ray goes along z
+fn triangle_hit(i: u32, triangle: Triangle, offset: u32, ray: Ray, interval: Interval,
+    hit: ptr<function, TriangleHit>) -> bool {
+    var v0 = vertex_pos(triangle.v0 + offset) - ray.orig;
+    var v1 = vertex_pos(triangle.v1 + offset) - ray.orig;
+    var v2 = vertex_pos(triangle.v2 + offset) - ray.orig;
+
+    let z = max_dim(abs(ray.dir));
+    let x = (z + 1) % 3;
+    let y = (x + 1) % 3;
+    v0 = permute(v0, x, y, z);
+    v1 = permute(v1, x, y, z);
+    v2 = permute(v2, x, y, z);
+
+    let sx = -ray.dir[x] / ray.dir[z];
+    let sy = -ray.dir[y] / ray.dir[z];
+    let sz = 1.0 / ray.dir[z];
+    v0 += vec3(v0.z * vec2(sx, sy), 0.0);
+    v1 += vec3(v1.z * vec2(sx, sy), 0.0);
+    v2 += vec3(v2.z * vec2(sx, sy), 0.0);
+
+    let e0 = v1.x * v2.y - v1.y * v2.x;
+    let e1 = v2.x * v0.y - v2.y * v0.x;
+    let e2 = v0.x * v1.y - v0.y * v1.x;
+    if (e0 < 0 || e1 < 0 || e2 < 0) && (e0 > 0 || e1 > 0 || e2 > 0) {
+        return false;
+    }
+    let det = e0 + e1 + e2;
+    if det == 0 {
+        return false;
+    }
+
+    v0.z *= sz;
+    v1.z *= sz;
+    v2.z *= sz;
+    let t = (e0 * v0.z + e1 * v1.z + e2 * v2.z) / det;
+    if !interval_surrounds(interval, t) {
+        return false;
+    }
+
+    *hit = TriangleHit(t, vec3(e0, e1, e2), det);
+    return true;
+}
+
+fn max_dim(v: vec3f) -> u32 {
+    if v.x > v.y && v.x > v.z {
+        return 0u;
+    } else if v.y > v.z {
+        return 1u;
+    } else {
+        return 2u;
+    }
+}
+
+fn permute(v: vec3f, x: u32, y: u32, z: u32) -> vec3f {
+    let vx = v[x];
+    let vy = v[y];
+    let vz = v[z];
+    return vec3(vx, vy, vz);
+}
@@ -0,0 +1,73 @@
+// The vertices of the compact layout, which take 20 bytes instead of 48
+///#chunked @group(2) @binding(0) vertices: array<Vertex>
+
+// The edges from the first vertex of each triangle, indexed like the triangles
+///#chunked @group(1) @binding(3) triangle_edges: array<TriangleEdges>
+
+// The position is unaligned, the normal is encoded with octahedral mapping into two snorm16 and the
+// texture coordinate as two half floats
+struct Vertex {
+    pos: array<f32, 3>,
+    normal: u32,
+    tex_coord: u32,
+}
+
+fn vertex_pos(i: u32) -> vec3f {
+    let pos = vertices(i).pos;
+    return vec3(pos[0], pos[1], pos[2]);
+}
+
+fn vertex_normal(i: u32) -> vec3f {
+    let f = unpack2x16snorm(vertices(i).normal);
+    var n = vec3(f, 1.0 - abs(f.x) - abs(f.y));
+    if n.z < 0 {
+        n = vec3((1.0 - abs(n.yx)) * select(vec2(-1.0), vec2(1.0), n.xy >= vec2(0.0)), n.z);
+    }
+    return normalize(n);
+}
+
+fn vertex_tex_coord(i: u32) -> vec2f {
+    return unpack2x16float(vertices(i).tex_coord);
+}
+
+// The edges as six half floats, `v1 - v0` then `v2 - v0`
+struct TriangleEdges {
+    edges: array<u32, 3>,
+}
+
+// How far outside of its edges a triangle is hit, so that the half-float edges, which move the
+// other vertices slightly, leave no cracks between the triangles
+const EDGE_TOLERANCE = 0.002;
+
+// The Moller-Trumbore test of the triangle with its precomputed edges
+fn triangle_hit(i: u32, triangle: Triangle, offset: u32, ray: Ray, interval: Interval,
+    hit: ptr<function, TriangleHit>) -> bool {
+    let packed = triangle_edges(i).edges;
+    let e0 = unpack2x16float(packed[0]);
+    let e1 = unpack2x16float(packed[1]);
+    let e2 = unpack2x16float(packed[2]);
+    let edge1 = vec3(e0, e1.x);
+    let edge2 = vec3(e1.y, e2);
+
+    let p = cross(ray.dir, edge2);
+    let det = dot(edge1, p);
+    if det == 0 {
+        return false;
+    }
+
+    let s = ray.orig - vertex_pos(triangle.v0 + offset);
+    let u = dot(s, p) / det;
+    let q = cross(s, edge1);
+    let v = dot(ray.dir, q) / det;
+    if u < -EDGE_TOLERANCE || v < -EDGE_TOLERANCE || u + v > 1.0 + EDGE_TOLERANCE {
+        return false;
+    }
+
+    let t = dot(edge2, q) / det;
+    if !interval_surrounds(interval, t) {
+        return false;
+    }
+
+    *hit = TriangleHit(t, vec3(1.0 - u - v, u, v), 1.0);
+    return true;
+}
@@ -6,6 +6,7 @@ struct Intersection {
     front: bool,
     tex_coord: vec2f,
     transform: u32,
+    primitive: u32,
     material: u32,
 }
 
@@ -0,0 +1,127 @@
+///#include "utils/color.wgsl"
+
+override ITERATIONS: u32;
+override STRENGTH: f32;
+
+const SIGMA_LUMINANCE: f32 = 4.0;
+const SIGMA_NORMAL: f32 = 128.0;
+const SIGMA_DEPTH: f32 = 0.05;
+
+@group(0) @binding(0)
+var src_texture: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(1)
+var albedo_texture: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(2)
+var normal_texture: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(3)
+var position_texture: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(4)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+struct Constants {
+    tile_size: vec2u,
+    iteration: u32,
+    // The samples of the radiance, which the AOVs have fewer of after resuming from a checkpoint
+    samples: u32,
+}
+
+var<push_constant> constants: Constants;
+
+struct Guide {
+    albedo: vec3f,
+    normal: vec3f,
+    depth: f32,
+    samples: f32,
+}
+
+fn load_guide(id: vec2u) -> Guide {
+    let albedo = textureLoad(albedo_texture, id);
+    let normal = textureLoad(normal_texture, id);
+    let samples = max(albedo.w, 1.0);
+
+    var guide = Guide();
+    guide.albedo = demodulation(albedo.xyz / samples);
+    if any(normal.xyz != vec3(0.0)) {
+        guide.normal = normalize(normal.xyz);
+    }
+    guide.depth = normal.w / samples;
+    guide.samples = samples;
+    return guide;
+}
+
+// The first iteration divides the radiance by the albedo and estimates the variance
+// of its mean from the second moment, which later iterations carry in the alpha channel. The moment
+// is averaged over the samples of the AOVs, but the mean is taken over all samples of the radiance.
+fn load_illumination(id: vec2u, guide: Guide) -> vec4f {
+    let color = textureLoad(src_texture, id);
+    if constants.iteration > 0u {
+        return color;
+    }
+
+    let illumination = color.xyz / guide.albedo;
+    let mean = luminance(illumination);
+    let moment = textureLoad(position_texture, id).w / guide.samples;
+    return vec4(illumination, max(moment - mean * mean, 0.0) / max(f32(constants.samples), 1.0));
+}
+
+fn normal_weight(a: vec3f, b: vec3f) -> f32 {
+    // Escaped rays have no normal
+    if all(a == b) {
+        return 1.0;
+    }
+    return pow(max(dot(a, b), 0.0), SIGMA_NORMAL);
+}
+
+// An à-trous wavelet iteration, whose taps are spread further apart every iteration
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= constants.tile_size) {
+        return;
+    }
+
+    var kernel = array(1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0);
+    let step = 1i << constants.iteration;
+
+    let guide = load_guide(id.xy);
+    let center = load_illumination(id.xy, guide);
+    let sigma = SIGMA_LUMINANCE * STRENGTH * sqrt(center.w) + 1e-6;
+
+    var color = vec3(0.0);
+    var variance = 0.0;
+    var weights = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let tap = vec2i(id.xy) + vec2(x, y) * step;
+            if any(tap < vec2(0)) || any(tap >= vec2i(constants.tile_size)) {
+                continue;
+            }
+
+            let tap_guide = load_guide(vec2u(tap));
+            let tap_color = load_illumination(vec2u(tap), tap_guide);
+
+            let depth_tolerance = SIGMA_DEPTH * guide.depth * f32(step) + 1e-4;
+            let weight = kernel[2 - abs(x)] * kernel[2 - abs(y)]
+                * normal_weight(guide.normal, tap_guide.normal)
+                * exp(-abs(guide.depth - tap_guide.depth) / depth_tolerance)
+                * exp(-abs(luminance(center.xyz) - luminance(tap_color.xyz)) / sigma);
+
+            color += weight * tap_color.xyz;
+            variance += weight * weight * tap_color.w;
+            weights += weight;
+        }
+    }
+    color /= weights;
+    variance /= weights * weights;
+
+    // The last iteration multiplies the albedo back
+    if constants.iteration == ITERATIONS - 1u {
+        textureStore(dst_texture, id.xy, vec4(color * guide.albedo, 1.0));
+    } else {
+        textureStore(dst_texture, id.xy, vec4(color, variance));
+    }
+}
@@ -0,0 +1,35 @@
+///#include "post_stage.wgsl"
+
+// The number of levels of the output channels
+override LEVELS: f32 = 255.0;
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+// The PCG hash by Jarzynski and Olano
+fn pcg(v: vec2u) -> vec2u {
+    var r = v * 1664525u + 1013904223u;
+    r.x += r.y * 1664525u;
+    r.y += r.x * 1664525u;
+    r ^= r >> vec2(16u);
+    r.x += r.y * 1664525u;
+    r.y += r.x * 1664525u;
+    r ^= r >> vec2(16u);
+    return r;
+}
+
+// Adds noise with a triangular distribution spanning two quantization steps, which hides banding
+// in smooth gradients
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    let random = vec2f(pcg(tile.origin + id.xy) >> vec2(8u)) / 16777216.0;
+    let noise = (random.x - random.y) / LEVELS;
+
+    let color = textureLoad(src_texture, id.xy).xyz + noise;
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
@@ -0,0 +1,19 @@
+///#include "post_stage.wgsl"
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+// The exposure in EV stops
+@group(0) @binding(2)
+var<uniform> exposure: f32;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    let color = textureLoad(src_texture, id.xy).xyz * exp2(exposure);
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
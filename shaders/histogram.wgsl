@@ -0,0 +1,39 @@
+const NUM_BINS: u32 = 256u;
+const MIN_LOG_LUMINANCE: f32 = -16.0;
+const MAX_LOG_LUMINANCE: f32 = 16.0;
+
+@group(0) @binding(0)
+var src_texture: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, NUM_BINS>;
+
+var<workgroup> local_histogram: array<atomic<u32>, NUM_BINS>;
+
+// The first bin counts the pixels darker than the range, which are left out of the percentile
+fn luminance_bin(color: vec3f) -> u32 {
+    let luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    if luminance < exp2(MIN_LOG_LUMINANCE) {
+        return 0u;
+    }
+
+    let t = (clamp(log2(luminance), MIN_LOG_LUMINANCE, MAX_LOG_LUMINANCE) - MIN_LOG_LUMINANCE)
+        / (MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE);
+    return 1u + min(u32(t * f32(NUM_BINS - 1u)), NUM_BINS - 2u);
+}
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32
+) {
+    if all(id.xy < textureDimensions(src_texture)) {
+        let color = textureLoad(src_texture, id.xy).xyz;
+        atomicAdd(&local_histogram[luminance_bin(color)], 1u);
+    }
+    workgroupBarrier();
+
+    // Each of the 256 invocations flushes one bin of the workgroup
+    atomicAdd(&histogram[index], atomicLoad(&local_histogram[index]));
+}
@@ -0,0 +1,51 @@
+///#include "post_stage.wgsl"
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(2)
+var lut_texture: texture_3d<f32>;
+
+struct LutDomain {
+    min: vec3f,
+    max: vec3f,
+}
+
+@group(0) @binding(3)
+var<uniform> lut_domain: LutDomain;
+
+// Interpolates the LUT trilinearly, with the colors outside of its domain clamped to the domain
+fn apply_lut(color: vec3f) -> vec3f {
+    let size = textureDimensions(lut_texture).x;
+    let t = clamp((color - lut_domain.min) / (lut_domain.max - lut_domain.min), vec3(0.0), vec3(1.0));
+    let p = t * f32(size - 1u);
+    let base = min(vec3u(p), vec3(size - 2u));
+    let f = p - vec3f(base);
+
+    let c000 = textureLoad(lut_texture, base, 0).xyz;
+    let c100 = textureLoad(lut_texture, base + vec3(1u, 0u, 0u), 0).xyz;
+    let c010 = textureLoad(lut_texture, base + vec3(0u, 1u, 0u), 0).xyz;
+    let c110 = textureLoad(lut_texture, base + vec3(1u, 1u, 0u), 0).xyz;
+    let c001 = textureLoad(lut_texture, base + vec3(0u, 0u, 1u), 0).xyz;
+    let c101 = textureLoad(lut_texture, base + vec3(1u, 0u, 1u), 0).xyz;
+    let c011 = textureLoad(lut_texture, base + vec3(0u, 1u, 1u), 0).xyz;
+    let c111 = textureLoad(lut_texture, base + vec3(1u, 1u, 1u), 0).xyz;
+
+    let c00 = mix(c000, c100, f.x);
+    let c10 = mix(c010, c110, f.x);
+    let c01 = mix(c001, c101, f.x);
+    let c11 = mix(c011, c111, f.x);
+    return mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+}
+
+// Grading LUTs map the encoded colors of the display
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    let color = apply_lut(textureLoad(src_texture, id.xy).xyz);
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
@@ -23,7 +23,7 @@ fn material_brdf(intersection: Intersection, n: vec3f, wi: vec3f, wo: vec3f) ->
 
     let alpha = roughness * roughness;
     let f0 = mix(vec3(0.04), base_color, metallic);
-    let f = f0 + (1.0 - f0) * pow(1.0 - vdoth, 5.0);
+    let f = f0 + (1.0 - f0) * pow(max(1.0 - vdoth, 0.0), 5.0);
 
     let alpha2 = alpha * alpha;
     let diffuse = (1.0 - f) / PI * mix(base_color, vec3(0.0), metallic) * base_color;
@@ -0,0 +1,12 @@
+// The bindings shared by the stages processing every pixel on its own
+
+@group(0) @binding(0)
+var src_texture: texture_storage_2d<rgba32float, read>;
+
+// The textures are sized for the largest tile, of which only the current one is processed
+struct Tile {
+    origin: vec2u,
+    size: vec2u,
+}
+
+var<push_constant> tile: Tile;
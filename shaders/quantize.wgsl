@@ -0,0 +1,14 @@
+///#include "post_stage.wgsl"
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba8unorm, write>;
+
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    textureStore(dst_texture, id.xy, vec4(textureLoad(src_texture, id.xy).xyz, 1.0));
+}
@@ -0,0 +1,16 @@
+///#include "post_stage.wgsl"
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+// 16-bit outputs are kept as floats, which are quantized when they are read back
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    let color = clamp(textureLoad(src_texture, id.xy).xyz, vec3(0.0), vec3(1.0));
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
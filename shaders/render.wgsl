@@ -6,11 +6,14 @@
 ///#include "scene/scene.wgsl"
 
 ///#include "utils/aabb.wgsl"
+///#include "utils/color.wgsl"
 ///#include "utils/constants.wgsl"
 ///#include "utils/interval.wgsl"
 ///#include "utils/rand.wgsl"
 
+///#include "aov.wgsl"
 ///#include "materials.wgsl"
+///#include "stats.wgsl"
 ///#include "textures.wgsl"
 
 override MAX_DEPTH: u32;
@@ -18,7 +21,14 @@ override MAX_DEPTH: u32;
 @group(0) @binding(0)
 var render_target: texture_storage_2d<rgba32float, read_write>;
 
-var<push_constant> sample: u32;
+struct Constants {
+    sample: u32,
+    image_width: u32,
+    tile_origin: vec2u,
+    tile_size: vec2u,
+}
+
+var<push_constant> constants: Constants;
 
 struct Path {
     coefficient: vec3f,
@@ -28,11 +38,16 @@ struct Path {
 @compute
 @workgroup_size(16, 16, 1)
 fn main(@builtin(global_invocation_id) id: vec3<u32>) {
-    let size = textureDimensions(render_target);
-    var rand_state = rand_init(id.xy, size, sample);
+    if any(id.xy >= constants.tile_size) {
+        return;
+    }
+
+    let pixel = constants.tile_origin + id.xy;
+    var rand_state = rand_init(pixel, constants.image_width, constants.sample);
 
-    var ray = camera_gen_ray(scene.camera, id.xy, &rand_state);
+    var ray = camera_gen_ray(scene.camera, pixel, &rand_state);
     var paths = array<Path, 50>();
+    var aov = Aov();
     var depth = 0u;
     for (; depth < MAX_DEPTH; depth++) {
         var intersection = Intersection();
@@ -44,6 +59,19 @@ fn main(@builtin(global_invocation_id) id: vec3<u32>) {
                          normal_in_tangent.y * intersection.bitangent +
                          normal_in_tangent.z * intersection.normal);
 
+            if depth == 0u {
+                // The camera looks along its negative z-axis, whose length scales the ray parameter
+                let forward = (scene.camera.transform * vec4(0.0, 0.0, -1.0, 0.0)).xyz;
+                aov = Aov(
+                    sample_texture(material.base_color_texture, intersection.tex_coord),
+                    normal,
+                    ray_at(ray, intersection.t),
+                    intersection.t * length(forward),
+                    intersection.primitive + 1u,
+                    intersection.material + 1u,
+                );
+            }
+
             let wi = normalize(normal + rand_sphere(&rand_state));
             let wo = -normalize(ray.dir);
 
@@ -60,6 +88,10 @@ fn main(@builtin(global_invocation_id) id: vec3<u32>) {
     }
 
     depth++;
+    if STATS {
+        // The loop ends after the maximum depth without a miss
+        stats_count_rays(min(depth, MAX_DEPTH));
+    }
     var color = vec3(1.0, 1.0, 1.0);
     for (; depth > 0; depth--) {
         let path = paths[depth - 1];
@@ -68,4 +100,7 @@ fn main(@builtin(global_invocation_id) id: vec3<u32>) {
 
     let prev_color = textureLoad(render_target, id.xy);
     textureStore(render_target, id.xy, prev_color + vec4(color, 1.0));
+    if AOVS {
+        aov_store(id.xy, aov, color);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+///#include "post_stage.wgsl"
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+// The number of samples accumulated so far, which may be less than `--samples` mid-render
+@group(0) @binding(2)
+var<uniform> samples: u32;
+
+// Averages the accumulated samples into the radiance the stages work with
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    let color = textureLoad(src_texture, id.xy).xyz / f32(samples);
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
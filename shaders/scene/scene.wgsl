@@ -1,11 +1,11 @@
 @group(1) @binding(0)
 var<uniform> scene: SceneUniform;
 
-@group(1) @binding(1)
-var<storage, read> triangles: array<Triangle>;
+// The triangles and the nodes may be split into several bindings, which are read with
+// `triangles(i)` and `bvh_nodes(i)`
+///#chunked @group(1) @binding(1) triangles: array<Triangle>
 
-@group(1) @binding(2)
-var<storage, read> bvh_nodes: array<BvhNode>;
+///#chunked @group(1) @binding(2) bvh_nodes: array<BvhNode>
 
 struct SceneUniform {
     camera: Camera,
@@ -35,13 +35,14 @@ fn scene_intersect(ray: Ray, intersection: ptr<function, Intersection>) -> bool
     loop {
         var interval = Interval(0.001, (*intersection).t);
 
+        let bvh_node = bvh_nodes(node);
         let left = node + 1;
-        let right = bvh_nodes[node].rigth_idx;
+        let right = bvh_node.rigth_idx;
 
-        if right == 0 || !aabb_intersect(bvh_nodes[node].aabb, ray, interval) {
+        if right == 0 || !aabb_intersect(bvh_node.aabb, ray, interval) {
             if right == 0 {
-                for (var i = bvh_nodes[node].triangle_start; i < bvh_nodes[node].triangle_end; i++) {
-                    if triangle_intersect(triangles[i], ray, intersection, interval) {
+                for (var i = bvh_node.triangle_start; i < bvh_node.triangle_end; i++) {
+                    if triangle_intersect(i, ray, intersection, interval) {
                         intersected = true;
                         interval = Interval(0.001, (*intersection).t);
                     }
@@ -0,0 +1,13 @@
+// Enabled only if the statistics of the render are collected
+override STATS: bool = false;
+
+// The low and the high word of the number of rays traced, since 64-bit atomics are not portable
+@group(0) @binding(5)
+var<storage, read_write> ray_count: array<atomic<u32>, 2>;
+
+fn stats_count_rays(rays: u32) {
+    let low = atomicAdd(&ray_count[0], rays);
+    if low > 0xffffffffu - rays {
+        atomicAdd(&ray_count[1], 1u);
+    }
+}
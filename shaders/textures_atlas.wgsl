@@ -0,0 +1,41 @@
+// The textures of the materials packed into the layers of an atlas, for adapters without binding
+// arrays. The environment map is bound on its own, since it has a different format.
+@group(4) @binding(0)
+var atlas: texture_2d_array<f32>;
+
+@group(4) @binding(1)
+var panorama: texture_2d<f32>;
+
+// The texels of a texture in the atlas
+struct AtlasRect {
+    origin: vec2u,
+    size: vec2u,
+    layer: u32,
+}
+
+@group(4) @binding(2)
+var<storage, read> atlas_rects: array<AtlasRect>;
+
+fn sample_panorama(idx: u32, uvw: vec3f) -> vec3f {
+    let texture_size = textureDimensions(panorama);
+
+    let theta = acos(-uvw.y);
+    let phi = atan2(-uvw.z, uvw.x) + PI;
+
+    let u = phi / (2.0 * PI);
+    let v = theta / PI;
+
+    let x = u32(u * f32(texture_size.x - 1));
+    let y = u32((1.0 - v) * f32(texture_size.y - 1));
+
+    return textureLoad(panorama, vec2(x, y), 0).rgb;
+}
+
+fn sample_texture(idx: u32, uv: vec2f) -> vec3f {
+    let rect = atlas_rects[idx];
+
+    let x = u32(fract(uv.x) * f32(rect.size.x - 1));
+    let y = u32(fract(uv.y) * f32(rect.size.y - 1));
+
+    return textureLoad(atlas, rect.origin + vec2(x, y), rect.layer, 0).rgb;
+}
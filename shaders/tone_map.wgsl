@@ -0,0 +1,18 @@
+///#include "post_stage.wgsl"
+///#include "tone_mapping.wgsl"
+///#include "color_space.wgsl"
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+// The stages following this one work with the encoded colors of the display
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    let color = encode_color(tone_map(textureLoad(src_texture, id.xy).xyz));
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
@@ -0,0 +1,92 @@
+override TONE_MAPPING: u32;
+override WHITE_POINT: f32 = 0.0;
+
+fn tone_map(color: vec3f) -> vec3f {
+    switch TONE_MAPPING {
+        case 1u: {
+            return tone_map_reinhard(color);
+        }
+        case 2u: {
+            return tone_map_aces(color);
+        }
+        case 3u: {
+            return tone_map_agx(color);
+        }
+        case 4u: {
+            return tone_map_filmic(color);
+        }
+        default: {
+            return clamp(color, vec3(0.0), vec3(1.0));
+        }
+    }
+}
+
+// A non-positive white point is treated as infinite, which gives the simple Reinhard operator
+fn tone_map_reinhard(color: vec3f) -> vec3f {
+    var numerator = color;
+    if WHITE_POINT > 0.0 {
+        numerator *= 1.0 + color / (WHITE_POINT * WHITE_POINT);
+    }
+    return numerator / (1.0 + color);
+}
+
+// Stephen Hill's fit of the ACES reference rendering and output transforms
+fn tone_map_aces(color: vec3f) -> vec3f {
+    let input = mat3x3f(
+        0.59719, 0.07600, 0.02840,
+        0.35458, 0.90834, 0.13383,
+        0.04823, 0.01566, 0.83777
+    );
+    let output = mat3x3f(
+        1.60475, -0.10208, -0.00327,
+        -0.53108, 1.10813, -0.07276,
+        -0.07367, -0.00605, 1.07602
+    );
+
+    let v = input * color;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp(output * (a / b), vec3(0.0), vec3(1.0));
+}
+
+// Troy Sobotka's AgX with the polynomial fit of its default contrast curve
+fn tone_map_agx(color: vec3f) -> vec3f {
+    let inset = mat3x3f(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    let outset = mat3x3f(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = log2(max(inset * color, vec3(1e-10)));
+    v = (clamp(v, vec3(min_ev), vec3(max_ev)) - min_ev) / (max_ev - min_ev);
+
+    let v2 = v * v;
+    let v4 = v2 * v2;
+    v = 15.5 * v4 * v2 - 40.14 * v4 * v + 31.96 * v4 - 6.868 * v2 * v + 0.4298 * v2 + 0.1191 * v - 0.00232;
+
+    // AgX outputs display-encoded values, which are linearized again for the output encoding
+    return pow(clamp(outset * v, vec3(0.0), vec3(1.0)), vec3(2.2));
+}
+
+// John Hable's filmic curve from Uncharted 2
+fn tone_map_filmic(color: vec3f) -> vec3f {
+    let white = 11.2;
+    return tone_map_filmic_partial(2.0 * color) / tone_map_filmic_partial(vec3(white));
+}
+
+fn tone_map_filmic_partial(x: vec3f) -> vec3f {
+    let a = 0.15;
+    let b = 0.50;
+    let c = 0.10;
+    let d = 0.20;
+    let e = 0.02;
+    let f = 0.30;
+    return (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f;
+}
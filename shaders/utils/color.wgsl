@@ -0,0 +1,8 @@
+fn luminance(color: vec3f) -> f32 {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Escaped rays and black surfaces have no albedo to be divided by, so their radiance is kept
+fn demodulation(albedo: vec3f) -> vec3f {
+    return select(albedo, vec3(1.0), albedo < vec3(1e-3));
+}
@@ -1,5 +1,5 @@
-fn rand_init(id: vec2u, size: vec2u, frame: u32) -> u32 {
-    let state = dot(id, vec2(1, size.x)) ^ jenkins_hash(frame);
+fn rand_init(id: vec2u, width: u32, frame: u32) -> u32 {
+    let state = dot(id, vec2(1, width)) ^ jenkins_hash(frame);
     return jenkins_hash(state);
 }
 
@@ -0,0 +1,26 @@
+///#include "post_stage.wgsl"
+
+override WIDTH: u32;
+override HEIGHT: u32;
+override STRENGTH: f32;
+
+@group(0) @binding(1)
+var dst_texture: texture_storage_2d<rgba32float, write>;
+
+// Darkens the image towards the corners by the cos⁴ law of natural vignetting, where the strength
+// is the squared tangent of the angle to the corners
+@compute
+@workgroup_size(16, 16, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if any(id.xy >= tile.size) {
+        return;
+    }
+
+    let size = vec2f(f32(WIDTH), f32(HEIGHT));
+    let position = vec2f(tile.origin + id.xy) + 0.5;
+    let distance = length(position - 0.5 * size) / length(0.5 * size);
+    let cos2 = 1.0 / (1.0 + STRENGTH * distance * distance);
+
+    let color = textureLoad(src_texture, id.xy).xyz * cos2 * cos2;
+    textureStore(dst_texture, id.xy, vec4(color, 1.0));
+}
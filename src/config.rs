@@ -1,36 +1,88 @@
 use std::fmt::{self, Display};
 use std::str::FromStr;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::settings::RenderSettings;
 
 #[derive(Parser)]
-#[command(version)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Config {
-    /// The image size of the output
-    #[arg(short, long, default_value_t = Size::new(400, 225))]
-    pub size: Size,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub settings: RenderSettings,
 
-    /// The path to the output
-    #[arg(short, long, default_value_t = String::from("output.png"))]
+    /// The path to the output, which holds the linear radiance if it is an .exr or .hdr file
+    #[arg(short, long, global = true, default_value_t = String::from("output.png"))]
     pub output: String,
 
-    /// The maximum depth of each camera ray
-    #[arg(long, default_value_t = 50)]
-    pub depth: u32,
+    /// The path to additionally write the linear radiance to as an OpenEXR or Radiance HDR file
+    #[arg(long, global = true)]
+    pub hdr_output: Option<String>,
+
+    /// The bits per channel of the output, which is inferred from the extension if unset
+    #[arg(long, global = true, value_enum)]
+    pub bit_depth: Option<BitDepth>,
 
-    /// The number of samples per pixel
-    #[arg(long, default_value_t = 1000)]
-    pub samples: u32,
+    /// The OpenEXR file to write all auxiliary passes to as layers, instead of a file for each
+    #[arg(long, value_parser = exr_path)]
+    pub aov_output: Option<String>,
 
-    /// The environment map of the scene to be rendered
-    #[arg(long, default_value_t = String::from("textures/indoor.hdr"))]
-    pub hdri: String,
+    /// The path to additionally write the image without denoising to
+    #[arg(long, requires = "denoise")]
+    pub noisy_output: Option<String>,
+
+    /// Write the statistics of the render to a JSON file next to the output
+    #[arg(long)]
+    pub stats: bool,
 
     /// The path to the glTF scene description file
-    pub scene: String,
+    #[arg(required_unless_present = "list_adapters")]
+    pub scene: Option<String>,
+
+    /// A render job in TOML or JSON, whose keys are the names of the options, overridden by flags
+    #[arg(long)]
+    pub job: Option<String>,
+
+    /// Print the job resolved from the job file and the flags instead of rendering it
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "toml"
+    )]
+    pub dump_config: Option<JobFormat>,
+
+    /// List the adapters of the selected backend and the features they lack instead of rendering
+    #[arg(long)]
+    pub list_adapters: bool,
 }
 
-#[derive(Clone)]
+#[derive(Subcommand)]
+pub enum Command {
+    /// Merge the checkpoints of distributed renders into the final image, which covers a single
+    /// tile
+    Merge {
+        /// The checkpoints to be merged
+        #[arg(required = true)]
+        checkpoints: Vec<String>,
+    },
+    /// Report the contents of a glTF scene and the problems which would fail its render, or the
+    /// metadata of a rendered PNG or EXR image
+    Inspect {
+        /// The path to the glTF scene description file or to the rendered image
+        path: String,
+    },
+}
+
+#[derive(Clone, Copy)]
 pub struct Size {
     pub width: u32,
     pub height: u32,
@@ -52,6 +104,9 @@ impl FromStr for Size {
         }
         let width = size[0].parse().map_err(|e| format!("{e}"))?;
         let height = size[1].parse().map_err(|e| format!("{e}"))?;
+        if width == 0 || height == 0 {
+            return Err(String::from("the size cannot be empty"));
+        }
         Ok(Self::new(width, height))
     }
 }
@@ -61,3 +116,242 @@ impl Display for Size {
         write!(f, "{}x{}", self.width, self.height)
     }
 }
+
+// The name of a value on the command line
+pub fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().unwrap().get_name().to_string()
+}
+
+fn exr_path(s: &str) -> Result<String, String> {
+    if !s.to_lowercase().ends_with(".exr") {
+        return Err(String::from("expected the path to an .exr file"));
+    }
+    Ok(s.to_string())
+}
+
+#[derive(Clone)]
+pub struct Region {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub normalized: bool,
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let parts: Vec<_> = s.split('+').collect();
+        if parts.len() != 3 {
+            return Err(String::from("expected a region in the form of WxH+X+Y"));
+        }
+        let size: Vec<_> = parts[0].split('x').collect();
+        if size.len() != 2 {
+            return Err(String::from("invalid number of dimensions found in string"));
+        }
+
+        let values = [size[0], size[1], parts[1], parts[2]];
+        let mut parsed = [0.0f32; 4];
+        for (value, parsed) in values.iter().zip(&mut parsed) {
+            *parsed = value.parse().map_err(|e| format!("{e}"))?;
+            if !parsed.is_finite() {
+                return Err(String::from("the region must be finite"));
+            }
+            if *parsed < 0.0 {
+                return Err(String::from("the region cannot be negative"));
+            }
+        }
+        let [width, height, x, y] = parsed;
+        if width == 0.0 || height == 0.0 {
+            return Err(String::from("the region cannot be empty"));
+        }
+
+        // Values written with a decimal point are fractions of the image size
+        let normalized = values.iter().any(|value| value.contains('.'));
+        if normalized && parsed.iter().any(|value| *value > 1.0) {
+            return Err(String::from("fractions of the image size cannot exceed 1"));
+        }
+
+        Ok(Self {
+            x,
+            y,
+            width,
+            height,
+            normalized,
+        })
+    }
+}
+
+#[derive(Clone, ValueEnum)]
+pub enum RegionOutput {
+    /// Output only the region
+    Crop,
+    /// Output the full image with black outside of the region
+    Black,
+    /// Output the full image with transparency outside of the region
+    Transparent,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum BitDepth {
+    #[value(name = "8")]
+    Eight,
+    #[value(name = "16")]
+    Sixteen,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    /// OpenGL or OpenGL ES
+    Gl,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum GeometryLayout {
+    /// Aligned full-precision vertices
+    Standard,
+    /// Vertices with unaligned positions, octahedral normals and half-float texture coordinates
+    Compact,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum JobFormat {
+    Toml,
+    Json,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Stage {
+    /// Scale the radiance by the exposure
+    Exposure,
+    /// Denoise the radiance with `--denoise`
+    Denoise,
+    /// Add the bloom with `--bloom`
+    Bloom,
+    /// Map the radiance to the encoded colors of the display
+    #[value(name = "tonemap")]
+    ToneMap,
+    /// Grade the colors with `--lut`
+    Lut,
+    /// Darken the corners with `--vignette`
+    Vignette,
+    /// Dither the colors with `--dither`
+    Dither,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Aov {
+    /// The base color of the first hit
+    Albedo,
+    /// The world-space shading normal of the first hit
+    Normal,
+    /// The distance of the first hit along the view direction
+    Depth,
+    /// The world-space position of the first hit
+    Position,
+    /// The index of the primitive of the first hit, which is 0 for the background
+    Primitive,
+    /// The index of the material of the first hit, which is 0 for the background
+    Material,
+}
+
+// The discriminants are the values of `TONE_MAPPING` in the post-processing shader
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ToneMapping {
+    /// Clamp the colors without tone mapping
+    #[value(alias = "clamp")]
+    None = 0,
+    /// Extended Reinhard with an optional white point
+    Reinhard = 1,
+    /// Stephen Hill's fit of ACES
+    Aces = 2,
+    /// Troy Sobotka's AgX
+    Agx = 3,
+    /// John Hable's filmic curve from Uncharted 2
+    Filmic = 4,
+}
+
+// The discriminants are the values of `COLOR_SPACE` in the post-processing shader
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorSpace {
+    /// sRGB with the piecewise sRGB transfer function
+    Srgb = 0,
+    /// Display P3 with the sRGB transfer function
+    #[value(alias = "p3")]
+    DisplayP3 = 1,
+    /// Rec.2020 with the Rec.709 transfer function
+    Rec2020 = 2,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(s: &str) -> Result<[f32; 4], String> {
+        let region = Region::from_str(s)?;
+        Ok([region.width, region.height, region.x, region.y])
+    }
+
+    #[test]
+    fn region_in_pixels() {
+        assert_eq!(region("40x30+10+5"), Ok([40.0, 30.0, 10.0, 5.0]));
+        assert!(!Region::from_str("40x30+10+5").unwrap().normalized);
+    }
+
+    #[test]
+    fn region_in_fractions() {
+        assert_eq!(region("0.5x0.25+0.5+0"), Ok([0.5, 0.25, 0.5, 0.0]));
+        assert!(Region::from_str("0.5x0.25+0.5+0").unwrap().normalized);
+    }
+
+    #[test]
+    fn malformed_region() {
+        for s in [
+            "",
+            "40x30",
+            "40x30+10",
+            "40x30+10+5+1",
+            "40+10+5",
+            "40x30x2+10+5",
+            "ax30+1+1",
+        ] {
+            assert!(region(s).is_err(), "{s} was accepted");
+        }
+    }
+
+    #[test]
+    fn out_of_range_region() {
+        assert_eq!(
+            region("0x30+0+0"),
+            Err(String::from("the region cannot be empty"))
+        );
+        assert_eq!(
+            region("40x30+-1+0"),
+            Err(String::from("the region cannot be negative"))
+        );
+        assert_eq!(
+            region("40xinf+0+0"),
+            Err(String::from("the region must be finite"))
+        );
+        assert_eq!(
+            region("0.5x1.5+0+0"),
+            Err(String::from("fractions of the image size cannot exceed 1"))
+        );
+    }
+
+    #[test]
+    fn size() {
+        let size = Size::from_str("1920x1080").unwrap();
+        assert_eq!((size.width, size.height), (1920, 1080));
+        assert!(Size::from_str("1920").is_err());
+        assert!(Size::from_str("1920x-1").is_err());
+        assert_eq!(
+            Size::from_str("0x1080").err(),
+            Some(String::from("the size cannot be empty"))
+        );
+    }
+}
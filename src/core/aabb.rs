@@ -45,6 +45,11 @@ impl Aabb3 {
     pub fn centroid(&self) -> Vec3 {
         (self.min + self.max) / 2.0
     }
+
+    pub fn surface_area(&self) -> f32 {
+        let diff = self.max - self.min;
+        2.0 * (diff.x * diff.y + diff.y * diff.z + diff.z * diff.x)
+    }
 }
 
 impl Default for Aabb3 {
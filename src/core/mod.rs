@@ -1,7 +1,23 @@
+use encase::ShaderSize;
+
+use crate::config::GeometryLayout;
+
 mod aabb;
 mod triangle;
 mod vertex;
 
 pub use aabb::Aabb3;
-pub use triangle::Triangle;
-pub use vertex::Vertex;
+pub use triangle::{Triangle, TriangleEdges};
+pub use vertex::{CompactVertex, Vertex};
+
+// The bytes of the triangles, their edges and the vertices on the device in a layout
+pub fn geometry_memory(layout: GeometryLayout, triangles: usize, vertices: usize) -> u64 {
+    let (triangle_size, vertex_size) = match layout {
+        GeometryLayout::Standard => (Triangle::SHADER_SIZE.get(), Vertex::SHADER_SIZE.get()),
+        GeometryLayout::Compact => (
+            Triangle::SHADER_SIZE.get() + TriangleEdges::SHADER_SIZE.get(),
+            CompactVertex::SHADER_SIZE.get(),
+        ),
+    };
+    triangles as u64 * triangle_size + vertices as u64 * vertex_size
+}
@@ -1,4 +1,5 @@
 use encase::ShaderType;
+use half::f16;
 
 use crate::primitives::Primitives;
 
@@ -20,3 +21,23 @@ impl Triangle {
             .union_point(primitives.vertex(self.primitive, self.v2).pos)
     }
 }
+
+// The edges of a triangle from its first vertex in the compact layout, as six half floats like
+// `pack2x16float`
+#[derive(ShaderType, Copy, Clone)]
+pub struct TriangleEdges {
+    pub edges: [u32; 3],
+}
+
+impl TriangleEdges {
+    pub fn new(triangle: &Triangle, primitives: &Primitives) -> Self {
+        let p0 = primitives.vertex(triangle.primitive, triangle.v0).pos;
+        let e1 = primitives.vertex(triangle.primitive, triangle.v1).pos - p0;
+        let e2 = primitives.vertex(triangle.primitive, triangle.v2).pos - p0;
+        let [a, b, c, d, e, f] =
+            [e1.x, e1.y, e1.z, e2.x, e2.y, e2.z].map(|x| f16::from_f32(x).to_bits() as u32);
+        Self {
+            edges: [a | b << 16, c | d << 16, e | f << 16],
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use encase::ShaderType;
 use glam::{Vec2, Vec3};
+use half::f16;
 
 #[derive(ShaderType, Copy, Clone)]
 pub struct Vertex {
@@ -7,3 +8,116 @@ pub struct Vertex {
     pub normal: Vec3,
     pub tex_coord: Vec2,
 }
+
+// A vertex of the compact layout, whose position is unaligned so that it takes 20 bytes instead of
+// 48
+#[derive(ShaderType, Copy, Clone)]
+pub struct CompactVertex {
+    pub pos: [f32; 3],
+    // Octahedral mapping into two snorm16, like `pack2x16snorm`
+    pub normal: u32,
+    // Two half floats, like `pack2x16float`
+    pub tex_coord: u32,
+}
+
+impl CompactVertex {
+    pub fn new(vertex: &Vertex) -> Self {
+        let [u, v] = vertex
+            .tex_coord
+            .to_array()
+            .map(|x| f16::from_f32(x).to_bits() as u32);
+        Self {
+            pos: vertex.pos.to_array(),
+            normal: octahedral_encode(vertex.normal),
+            tex_coord: u | v << 16,
+        }
+    }
+}
+
+// Projects the normal onto an octahedron, whose lower half is folded over the upper one
+fn octahedral_encode(normal: Vec3) -> u32 {
+    let n = normal / normal.abs().element_sum();
+    let xy = match n.z >= 0.0 {
+        true => n.truncate(),
+        false => {
+            (Vec2::ONE - Vec2::new(n.y, n.x).abs())
+                * Vec2::select(n.truncate().cmpge(Vec2::ZERO), Vec2::ONE, Vec2::NEG_ONE)
+        }
+    };
+    let [x, y] = xy
+        .to_array()
+        .map(|x| (x.clamp(-1.0, 1.0) * 32767.0).round() as i16 as u16 as u32);
+    x | y << 16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The quantization of the snorm16 components
+    const TOLERANCE: f32 = 1e-4;
+
+    // Mirrors `vertex_normal` of the compact shaders
+    fn octahedral_decode(normal: u32) -> Vec3 {
+        let f = Vec2::new(
+            normal as u16 as i16 as f32,
+            (normal >> 16) as u16 as i16 as f32,
+        );
+        let f = (f / 32767.0).clamp(Vec2::NEG_ONE, Vec2::ONE);
+        let mut n = f.extend(1.0 - f.x.abs() - f.y.abs());
+        if n.z < 0.0 {
+            let sign = Vec2::select(n.truncate().cmpge(Vec2::ZERO), Vec2::ONE, Vec2::NEG_ONE);
+            n = ((Vec2::ONE - Vec2::new(n.y, n.x).abs()) * sign).extend(n.z);
+        }
+        n.normalize()
+    }
+
+    fn assert_round_trip(normal: Vec3) {
+        let decoded = octahedral_decode(octahedral_encode(normal));
+        assert!(
+            (decoded - normal).abs().max_element() < TOLERANCE,
+            "{normal} decoded as {decoded}"
+        );
+    }
+
+    #[test]
+    fn poles_and_axes() {
+        for normal in [
+            Vec3::X,
+            Vec3::Y,
+            Vec3::Z,
+            Vec3::NEG_X,
+            Vec3::NEG_Y,
+            Vec3::NEG_Z,
+        ] {
+            assert_round_trip(normal);
+        }
+    }
+
+    #[test]
+    fn lower_hemisphere() {
+        for normal in [
+            Vec3::new(0.3, 0.4, -0.8),
+            Vec3::new(-0.3, 0.4, -0.8),
+            Vec3::new(0.3, -0.4, -0.8),
+            Vec3::new(-0.3, -0.4, -0.8),
+            Vec3::new(1.0, 1.0, -0.001),
+            Vec3::new(-1.0, 0.0, -1.0),
+        ] {
+            assert_round_trip(normal.normalize());
+        }
+    }
+
+    #[test]
+    fn sphere() {
+        // A Fibonacci sphere, which covers both hemispheres evenly
+        let count = 1000;
+        let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+        for i in 0..count {
+            let z = 1.0 - 2.0 * (i as f32 + 0.5) / count as f32;
+            let r = (1.0 - z * z).sqrt();
+            let phi = i as f32 * golden_angle;
+            assert_round_trip(Vec3::new(r * phi.cos(), r * phi.sin(), z));
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    error,
+    fmt::{self, Display},
+    io,
+};
+
+// The failures of loading and rendering a scene, with the context of where they occurred
+#[derive(Debug)]
+pub enum Error {
+    // No adapter is available
+    Adapter(String),
+    // The adapter lacks a feature or a limit required by the renderer
+    Device(wgpu::RequestDeviceError),
+    // A shader or a pipeline failed the validation of the device
+    Shader {
+        shader: String,
+        message: String,
+    },
+    Gltf {
+        path: String,
+        source: gltf::Error,
+    },
+    // A glTF document which can't be rendered as a whole
+    Scene(String),
+    // An image of the scene or the environment map, named by its URI or index
+    Texture {
+        image: String,
+        reason: String,
+    },
+    Material {
+        material: String,
+        reason: String,
+    },
+    Primitive {
+        node: String,
+        primitive: usize,
+        reason: String,
+    },
+    Camera {
+        node: String,
+        reason: String,
+    },
+    // The buffers of a scene which the device can't bind, described by the limits they exceed
+    Limits(Vec<String>),
+    Encoding(encase::internal::Error),
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    Image {
+        path: String,
+        source: image::ImageError,
+    },
+    Exr {
+        path: String,
+        source: exr::error::Error,
+    },
+    Checkpoint(String),
+    Lut {
+        path: String,
+        reason: String,
+    },
+    Job {
+        path: String,
+        reason: String,
+    },
+    // The metadata of a rendered image which can't be read
+    Metadata {
+        path: String,
+        reason: String,
+    },
+    // Settings which contradict each other or the output
+    Settings(String),
+    Readback(wgpu::BufferAsyncError),
+}
+
+impl Error {
+    pub fn io(path: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |source| Self::Io {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn image(path: &str) -> impl FnOnce(image::ImageError) -> Self + '_ {
+        move |source| Self::Image {
+            path: path.to_string(),
+            source,
+        }
+    }
+
+    pub fn exr(path: &str) -> impl FnOnce(exr::error::Error) -> Self + '_ {
+        move |source| Self::Exr {
+            path: path.to_string(),
+            source,
+        }
+    }
+}
+
+// Names glTF objects by their name if they have one, and by their index otherwise
+pub fn describe(name: Option<&str>, index: usize) -> String {
+    match name {
+        Some(name) => format!("\"{name}\""),
+        None => format!("#{index}"),
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Adapter(message) => write!(f, "no adapter is available: {message}"),
+            Self::Device(error) => write!(f, "the device cannot be created: {error}"),
+            Self::Shader { shader, message } => {
+                write!(f, "the {shader} shader failed to compile: {message}")
+            }
+            Self::Gltf { path, source } => write!(f, "{path} cannot be loaded: {source}"),
+            Self::Texture { image, reason } => write!(f, "image {image} {reason}"),
+            Self::Material { material, reason } => write!(f, "material {material} {reason}"),
+            Self::Primitive {
+                node,
+                primitive,
+                reason,
+            } => write!(f, "primitive {primitive} of node {node} {reason}"),
+            Self::Camera { node, reason } => write!(f, "the camera of node {node} {reason}"),
+            Self::Limits(exceeded) => write!(
+                f,
+                "the scene exceeds the limits of the device: {}",
+                exceeded.join("; ")
+            ),
+            Self::Encoding(error) => write!(f, "a buffer cannot be encoded: {error}"),
+            Self::Io { path, source } => write!(f, "{path}: {source}"),
+            Self::Image { path, source } => write!(f, "{path}: {source}"),
+            Self::Exr { path, source } => write!(f, "{path}: {source}"),
+            Self::Scene(message) | Self::Checkpoint(message) | Self::Settings(message) => {
+                write!(f, "{message}")
+            }
+            Self::Lut { path, reason } => write!(f, "the LUT in {path} {reason}"),
+            Self::Job { path, reason } => write!(f, "the job in {path} {reason}"),
+            Self::Metadata { path, reason } => write!(f, "{path} {reason}"),
+            Self::Readback(error) => write!(f, "the result cannot be read back: {error}"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Device(error) => Some(error),
+            Self::Gltf { source, .. } => Some(source),
+            Self::Encoding(error) => Some(error),
+            Self::Io { source, .. } => Some(source),
+            Self::Image { source, .. } => Some(source),
+            Self::Exr { source, .. } => Some(source),
+            Self::Readback(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for Error {
+    fn from(error: wgpu::RequestDeviceError) -> Self {
+        Self::Device(error)
+    }
+}
+
+impl From<encase::internal::Error> for Error {
+    fn from(error: encase::internal::Error) -> Self {
+        Self::Encoding(error)
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for Error {
+    fn from(error: wgpu::BufferAsyncError) -> Self {
+        Self::Readback(error)
+    }
+}
@@ -0,0 +1,368 @@
+use std::{ffi::OsString, fs};
+
+use clap::{
+    error::ErrorKind, parser::ValueSource, Arg, ArgAction, ArgMatches, CommandFactory,
+    FromArgMatches,
+};
+use toml::{Table, Value};
+
+use crate::{
+    config::{Config, JobFormat},
+    error::Error,
+};
+
+// A render job resolved from the job file and the command line, where the flags take precedence
+pub struct Job {
+    pub config: Config,
+    matches: ArgMatches,
+}
+
+impl Job {
+    // The keys of the job file are turned into flags in front of the command line, so that they
+    // are validated by clap like any other flag. The help and the version are printed instead of
+    // a job, and the caller decides whether to exit.
+    pub fn parse(args: Vec<OsString>) -> Result<Option<Self>, Error> {
+        let command = Config::command();
+        // The command line is parsed leniently first to find the job file and the flags it sets,
+        // and is left to the strict parsing if even that fails
+        let matches = command
+            .clone()
+            .ignore_errors(true)
+            .try_get_matches_from(&args);
+
+        let mut job_args = Vec::new();
+        let matches = matches.ok();
+        let job = matches
+            .as_ref()
+            .and_then(|matches| Some((matches, matches.get_one::<String>("job")?)));
+        if let Some((matches, path)) = job {
+            for (key, value) in load(path)? {
+                let id = key.replace('-', "_");
+                let arg = command
+                    .get_arguments()
+                    .find(|arg| arg.get_id() == id.as_str() && is_job_key(arg))
+                    .ok_or_else(|| Error::Job {
+                        path: path.clone(),
+                        reason: format!("has the unknown key {key}"),
+                    })?;
+                if matches.value_source(&id) == Some(ValueSource::CommandLine) {
+                    continue;
+                }
+                let value = flag_value(arg, &value).ok_or_else(|| Error::Job {
+                    path: path.clone(),
+                    reason: format!("has an invalid value for {key}"),
+                })?;
+                job_args.extend(value);
+            }
+        }
+
+        let args = args[..1]
+            .iter()
+            .cloned()
+            .chain(job_args)
+            .chain(args[1..].iter().cloned());
+        let matches = match command.try_get_matches_from(args) {
+            Ok(matches) => matches,
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+                ) =>
+            {
+                // Like clap, a closed output is not an error
+                let _ = error.print();
+                return Ok(None);
+            }
+            Err(error) => return Err(usage_error(error)),
+        };
+        let config = Config::from_arg_matches(&matches).map_err(usage_error)?;
+        Ok(Some(Self { config, matches }))
+    }
+
+    // Writes every option with its resolved value, including the defaults
+    pub fn dump(&self, format: JobFormat) -> String {
+        let mut table = Table::new();
+        for arg in Config::command()
+            .get_arguments()
+            .filter(|arg| is_job_key(arg))
+        {
+            let id = arg.get_id().as_str();
+            let value = match arg.get_action() {
+                ArgAction::SetTrue => Value::Boolean(self.matches.get_flag(id)),
+                _ => {
+                    let Some(raw) = self.matches.get_raw(id) else {
+                        continue;
+                    };
+                    let mut values: Vec<_> = raw
+                        .map(|value| self.typed_value(id, &value.to_string_lossy()))
+                        .collect();
+                    match arg.get_action() {
+                        ArgAction::Append => Value::Array(values),
+                        _ => values.remove(0),
+                    }
+                }
+            };
+            table.insert(id.to_string(), value);
+        }
+
+        match format {
+            // Tables can't be nested, since all values are scalars or arrays of them
+            JobFormat::Toml => toml::to_string(&table).unwrap(),
+            JobFormat::Json => serde_json::to_string_pretty(&table).unwrap() + "\n",
+        }
+    }
+
+    // Numbers are written as such if the option parses them
+    fn typed_value(&self, id: &str, raw: &str) -> Value {
+        if let Ok(Some(value)) = self.matches.try_get_one::<u32>(id) {
+            Value::Integer(*value as i64)
+        } else if let Ok(Some(value)) = self.matches.try_get_one::<f32>(id) {
+            // The shortest representation of the f32 is kept
+            Value::Float(value.to_string().parse().unwrap())
+        } else {
+            Value::String(raw.to_string())
+        }
+    }
+}
+
+// The message of clap without its prefix, which the error is displayed with anyway
+fn usage_error(error: clap::Error) -> Error {
+    let message = error.to_string();
+    let message = message.strip_prefix("error: ").unwrap_or(&message);
+    Error::Settings(message.trim_end().to_string())
+}
+
+// JSON jobs are recognized by their extension, and everything else is read as TOML
+fn load(path: &str) -> Result<Table, Error> {
+    let text = fs::read_to_string(path).map_err(Error::io(path))?;
+    let table = if path.to_lowercase().ends_with(".json") {
+        serde_json::from_str(&text).map_err(|error| error.to_string())
+    } else {
+        toml::from_str(&text).map_err(|error| error.to_string())
+    };
+    table.map_err(|error| Error::Job {
+        path: path.to_string(),
+        reason: format!("cannot be parsed: {}", error.trim_end()),
+    })
+}
+
+// The job file can't refer to itself or print itself
+fn is_job_key(arg: &Arg) -> bool {
+    !matches!(
+        arg.get_id().as_str(),
+        "job" | "dump_config" | "list_adapters"
+    ) && !matches!(
+        arg.get_action(),
+        ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version
+    )
+}
+
+// Arrays are joined like the comma-separated values of the command line
+fn flag_value(arg: &Arg, value: &Value) -> Option<Vec<OsString>> {
+    let scalar = |value: &Value| match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Integer(value) => Some(value.to_string()),
+        Value::Float(value) => Some(value.to_string()),
+        _ => None,
+    };
+    let value = match (arg.get_action(), value) {
+        (ArgAction::SetTrue, Value::Boolean(true)) => return Some(vec![flag(arg).into()]),
+        (ArgAction::SetTrue, Value::Boolean(false)) => return Some(Vec::new()),
+        (ArgAction::SetTrue, _) => return None,
+        (_, Value::Array(values)) if values.is_empty() => return Some(Vec::new()),
+        (_, Value::Array(values)) => values
+            .iter()
+            .map(scalar)
+            .collect::<Option<Vec<_>>>()?
+            .join(","),
+        (_, value) => scalar(value)?,
+    };
+    match arg.is_positional() {
+        true => Some(vec![value.into()]),
+        false => Some(vec![format!("{}={value}", flag(arg)).into()]),
+    }
+}
+
+fn flag(arg: &Arg) -> String {
+    // Options of the settings all have long names
+    format!("--{}", arg.get_long().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::config::Aov;
+
+    fn arg(id: &str) -> Arg {
+        Config::command()
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)
+            .unwrap()
+            .clone()
+    }
+
+    fn value(id: &str, value: Value) -> Option<Vec<String>> {
+        let args = flag_value(&arg(id), &value)?;
+        Some(
+            args.into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect(),
+        )
+    }
+
+    // Parses the command line with the job written to a temporary file of the given extension
+    fn parse_job(name: &str, job: &str, args: &[&str]) -> Result<Job, Error> {
+        let path = env::temp_dir().join(format!("prisma-{}-{name}", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, job).unwrap();
+        let mut job_args = vec!["--job", &path];
+        job_args.extend(args);
+        let result = parse(&job_args);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn parse(args: &[&str]) -> Result<Job, Error> {
+        let args = ["prisma"].iter().chain(args).map(OsString::from).collect();
+        Ok(Job::parse(args)?.unwrap())
+    }
+
+    fn reason(result: Result<Job, Error>) -> String {
+        match result {
+            Err(Error::Job { reason, .. }) => reason,
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(_) => panic!("the job was accepted"),
+        }
+    }
+
+    #[test]
+    fn scalar_values() {
+        assert_eq!(
+            value("samples", Value::Integer(64)).unwrap(),
+            ["--samples=64"]
+        );
+        assert_eq!(
+            value("exposure", Value::Float(-0.5)).unwrap(),
+            ["--exposure=-0.5"]
+        );
+        assert_eq!(
+            value("scene", Value::String("scene.gltf".into())).unwrap(),
+            ["scene.gltf"]
+        );
+        assert_eq!(value("samples", Value::Boolean(true)), None);
+    }
+
+    #[test]
+    fn flag_values() {
+        assert_eq!(
+            value("denoise", Value::Boolean(true)).unwrap(),
+            ["--denoise"]
+        );
+        assert!(value("denoise", Value::Boolean(false)).unwrap().is_empty());
+        assert_eq!(value("denoise", Value::String("true".into())), None);
+    }
+
+    #[test]
+    fn array_values() {
+        let aovs = Value::Array(vec!["albedo".into(), "normal".into()]);
+        assert_eq!(value("aovs", aovs).unwrap(), ["--aovs=albedo,normal"]);
+        assert!(value("aovs", Value::Array(Vec::new())).unwrap().is_empty());
+        let nested = Value::Array(vec![Value::Array(vec!["albedo".into()])]);
+        assert_eq!(value("aovs", nested), None);
+    }
+
+    #[test]
+    fn command_line_overrides_job() {
+        let job = parse_job(
+            "override.toml",
+            "scene = \"job.gltf\"\nsamples = 64\ndenoise = true\n",
+            &["--samples", "8"],
+        )
+        .unwrap();
+        assert_eq!(job.config.scene.as_deref(), Some("job.gltf"));
+        assert_eq!(job.config.settings.samples, 8);
+        assert!(job.config.settings.denoise);
+    }
+
+    #[test]
+    fn json_job() {
+        let job = parse_job(
+            "job.json",
+            r#"{ "scene": "job.gltf", "aovs": ["depth"], "exposure": -1.5 }"#,
+            &[],
+        )
+        .unwrap();
+        assert!(job.config.settings.aovs == [Aov::Depth]);
+        assert_eq!(job.config.settings.exposure, -1.5);
+    }
+
+    #[test]
+    fn invalid_job() {
+        assert_eq!(
+            reason(parse_job("unknown.toml", "sample = 64\n", &["scene.gltf"])),
+            "has the unknown key sample"
+        );
+        assert_eq!(
+            reason(parse_job(
+                "self.toml",
+                "job = \"other.toml\"\n",
+                &["scene.gltf"]
+            )),
+            "has the unknown key job"
+        );
+        assert_eq!(
+            reason(parse_job("invalid.toml", "denoise = 1\n", &["scene.gltf"])),
+            "has an invalid value for denoise"
+        );
+        assert!(
+            reason(parse_job("syntax.toml", "samples =\n", &["scene.gltf"]))
+                .starts_with("cannot be parsed: ")
+        );
+    }
+
+    #[test]
+    fn usage_errors() {
+        match parse(&["scene.gltf", "--samples", "many"]) {
+            Err(Error::Settings(message)) => assert!(message.starts_with("invalid value 'many'")),
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(_) => panic!("the command line was accepted"),
+        }
+    }
+
+    #[test]
+    fn dump() {
+        let job = parse(&["scene.gltf", "--samples", "8", "--aovs", "albedo,normal"]).unwrap();
+        let dump = job.dump(JobFormat::Toml);
+        let table: Table = toml::from_str(&dump).unwrap();
+        assert_eq!(table["scene"], Value::String("scene.gltf".into()));
+        assert_eq!(table["samples"], Value::Integer(8));
+        assert_eq!(table["denoise_strength"], Value::Float(1.0));
+        assert_eq!(table["denoise"], Value::Boolean(false));
+        assert_eq!(
+            table["aovs"],
+            Value::Array(vec!["albedo".into(), "normal".into()])
+        );
+        assert!(!table.contains_key("job"));
+        assert!(!table.contains_key("camera"));
+
+        let json: Table = serde_json::from_str(&job.dump(JobFormat::Json)).unwrap();
+        assert_eq!(json, table);
+    }
+
+    #[test]
+    fn dump_round_trip() {
+        let job = parse(&[
+            "scene.gltf",
+            "--exposure=-0.3",
+            "--bloom",
+            "--size",
+            "64x32",
+        ])
+        .unwrap();
+        let dump = job.dump(JobFormat::Toml);
+        let resumed = parse_job("dump.toml", &dump, &[]).unwrap();
+        assert_eq!(resumed.dump(JobFormat::Toml), dump);
+    }
+}
@@ -1,7 +1,15 @@
 pub mod config;
 pub mod core;
+pub mod error;
+pub mod job;
 pub mod materials;
+pub mod output;
 pub mod primitives;
 pub mod render;
 pub mod scene;
+pub mod settings;
 pub mod textures;
+
+pub use error::Error;
+pub use render::{render, RenderOutput, RenderStats, SceneStats};
+pub use settings::RenderSettings;
@@ -1,86 +1,189 @@
-use std::error::Error;
+use std::{env, process::ExitCode};
 
-use clap::Parser;
 use console::Emoji;
 use prisma::{
-    config::Config,
-    render::{BindGroupLayoutSet, BindGroupSet, PostProcessor, RenderContext, Renderer},
-    scene::Scene,
+    config::{Command, Config, Size},
+    job::Job,
+    output::{self, Metadata},
+    render::{Checkpoint, PostProcessor, RenderContext, Tile},
+    Error, RenderOutput,
 };
 
-fn build_scene(
+// The exposure metered from the first tile is printed, so that it can be locked with `--exposure`
+fn print_exposure(exposure: Option<f32>) {
+    if let Some(exposure) = exposure {
+        println!("Auto exposure: {exposure:+.2} EV");
+    }
+}
+
+fn merge(
     context: &RenderContext,
-    config: &Config,
-) -> Result<(BindGroupLayoutSet, BindGroupSet), Box<dyn Error>> {
-    let (document, buffers, images) = gltf::import(&config.scene)?;
+    config: &mut Config,
+    checkpoints: &[String],
+) -> Result<(), Error> {
+    println!(
+        "{} {} Merging the checkpoints...",
+        console::style("[1/3]").bold().dim(),
+        Emoji("🧩 ", "")
+    );
+    let checkpoints = checkpoints
+        .iter()
+        .map(|path| Checkpoint::load(path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let checkpoint = Checkpoint::merge(checkpoints)?;
+    println!(
+        "Merged {} samples with indices {}..{}",
+        checkpoint.samples, checkpoint.sample_offset, checkpoint.sample_index
+    );
 
-    let mut scene = Scene::new(context);
-    scene.load(
-        config,
-        &document.scenes().next().unwrap(),
-        &buffers,
-        &images,
+    println!(
+        "{} {} Applying post-processing effects...",
+        console::style("[2/3]").bold().dim(),
+        Emoji("🌟 ", "")
     );
+    config.settings.size = Size::new(checkpoint.width, checkpoint.height);
+    config.settings.samples = checkpoint.samples;
+    let hdr_path = output::hdr_path(config)?;
+    let image = if output::is_hdr(&config.output) {
+        None
+    } else {
+        let post_processor = PostProcessor::new(context, &config.settings)?;
+        let texture = checkpoint.create_texture(context);
+        let window = Tile::window(&config.settings);
+        post_processor.post_process(&texture, checkpoint.samples, &window, None)?;
+        print_exposure(post_processor.metered_exposure());
+        Some(pollster::block_on(post_processor.retrieve_result(&window))?)
+    };
 
-    let hdri = scene.textures.load_texture_hdr(&config.hdri)?;
-    scene.set_hdri(hdri);
+    println!(
+        "{} {} Exporting the image...",
+        console::style("[3/3]").bold().dim(),
+        Emoji("🎞️  ", "")
+    );
+    let metadata = Metadata::merge(&config.settings, &checkpoint);
+    if let Some(image) = image {
+        output::save(&image, &config.output, config, &metadata)?;
+    }
+    if let Some(path) = hdr_path {
+        let Checkpoint {
+            width,
+            height,
+            samples,
+            data,
+            ..
+        } = checkpoint;
+        let radiance = output::radiance(width, height, data, samples);
+        output::save_hdr(&radiance, path, &metadata)?;
+    }
 
-    let (scene_bind_group_layout, scene_bind_group) = scene.build(context)?;
-    let (primitive_bind_group_layout, primitive_bind_group) = scene.primitives.build(context)?;
-    let (material_bind_group_layout, material_bind_group) = scene.materials.build(context)?;
-    let (texture_bind_group_layout, texture_bind_group) = scene.textures.build();
+    Ok(())
+}
 
-    let bind_group_layout_set = BindGroupLayoutSet {
-        scene: scene_bind_group_layout,
-        primitive: primitive_bind_group_layout,
-        material: material_bind_group_layout,
-        texture: texture_bind_group_layout,
-    };
-    let bind_group_set = BindGroupSet {
-        scene: scene_bind_group,
-        primitive: primitive_bind_group,
-        material: material_bind_group,
-        texture: texture_bind_group,
-    };
-    Ok((bind_group_layout_set, bind_group_set))
+// The inspection doesn't need a device, so that scenes can be checked before they are submitted
+fn inspect(path: &str) -> Result<(), Error> {
+    // Rendered images are told apart from scenes by their extension
+    if output::has_metadata(path) {
+        print!("{}", Metadata::read(path)?);
+        return Ok(());
+    }
+    let report = prisma::scene::inspect(path)?;
+    print!("{report}");
+    match report.errors.len() {
+        0 => Ok(()),
+        errors => Err(Error::Scene(format!(
+            "{path} has {errors} problems which would fail its render"
+        ))),
+    }
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
-    let config = Config::parse();
+fn run() -> Result<(), Error> {
+    let Some(job) = Job::parse(env::args_os().collect())? else {
+        return Ok(());
+    };
+    if let Some(format) = job.config.dump_config {
+        print!("{}", job.dump(format));
+        return Ok(());
+    }
+    if job.config.list_adapters {
+        let adapters = RenderContext::list_adapters(job.config.settings.backend);
+        if adapters.is_empty() {
+            println!("No adapters were found");
+        }
+        for (i, adapter) in adapters.iter().enumerate() {
+            println!("{i}: {adapter}");
+        }
+        return Ok(());
+    }
+    let mut config = job.config;
+    config.settings.bit_depth = output::bit_depth(&config)?;
+    config.settings.keep_noisy = config.noisy_output.is_some();
+    config.settings.collect_stats = config.stats;
 
-    let context = pollster::block_on(RenderContext::try_new())?;
-    println!(
-        "{} {} Parsing and loading the scene...",
-        console::style("[1/4]").bold().dim(),
-        Emoji("📜 ", "")
-    );
-    let (bind_group_layout_set, bind_group_set) = build_scene(&context, &config)?;
+    match config.command.take() {
+        Some(Command::Merge { checkpoints }) => {
+            let context = pollster::block_on(RenderContext::try_new(&config.settings))?;
+            return merge(&context, &mut config, &checkpoints);
+        }
+        Some(Command::Inspect { path }) => return inspect(&path),
+        None => {}
+    }
+    let Some(scene_path) = config.scene.as_deref() else {
+        return Err(Error::Settings(String::from(
+            "the scene to be rendered is missing",
+        )));
+    };
 
-    let renderer = Renderer::new(&context, &config, bind_group_layout_set);
     println!(
-        "{} {} Taking samples of path-traced rays...",
-        console::style("[2/4]").bold().dim(),
+        "{} {} Rendering the scene...",
+        console::style("[1/2]").bold().dim(),
         Emoji("📷 ", "")
     );
-    renderer.render(bind_group_set);
-
-    println!(
-        "{} {} Applying post-processing effects...",
-        console::style("[3/4]").bold().dim(),
-        Emoji("🌟 ", "")
-    );
-    let post_processor = PostProcessor::new(&context, &config);
-    post_processor.post_process(renderer.render_target());
+    let hdr_path = output::hdr_path(&config)?;
+    let RenderOutput {
+        hdr_image,
+        image,
+        noisy_image,
+        aovs,
+        stats,
+    } = prisma::render(scene_path, &config.settings)?;
+    if let (Some(samples), Some(path)) = (stats.resumed_samples, &config.settings.checkpoint) {
+        println!("Resumed from {samples} samples in {path}");
+    }
+    print_exposure(stats.exposure);
+    let metadata = Metadata::render(scene_path, &config.settings, &stats);
 
-    let image = pollster::block_on(post_processor.retrieve_result())?.unwrap();
     println!(
         "{} {} Exporting the image...",
-        console::style("[4/4]").bold().dim(),
+        console::style("[2/2]").bold().dim(),
         Emoji("🎞️  ", "")
     );
-    image.save(config.output)?;
-
+    if !output::is_hdr(&config.output) {
+        output::save(&image, &config.output, &config, &metadata)?;
+    }
+    if let (Some(noisy_image), Some(path)) = (noisy_image, &config.noisy_output) {
+        output::save(&noisy_image, path, &config, &metadata)?;
+    }
+    if let Some(path) = hdr_path {
+        output::save_hdr(&hdr_image, path, &metadata)?;
+    }
+    if let Some(aovs) = aovs {
+        output::save_aovs(&aovs, &config, &metadata)?;
+    }
+    if config.stats {
+        output::save_stats(&stats, &config)?;
+    }
 
     Ok(())
 }
+
+fn main() -> ExitCode {
+    env_logger::init();
+    // The error is displayed with its context rather than debug-printed
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -1,6 +1,9 @@
 use encase::{ShaderType, StorageBuffer};
 
-use crate::render::RenderContext;
+use crate::{
+    error::{describe, Error},
+    render::RenderContext,
+};
 
 #[derive(ShaderType)]
 pub struct Material {
@@ -20,20 +23,41 @@ impl Materials {
         Self::default()
     }
 
-    pub fn add(&mut self, material: &gltf::Material) -> Option<u32> {
+    // Every texture of the material is required
+    pub fn add(&mut self, material: &gltf::Material) -> Result<u32, Error> {
+        let missing = |texture| Error::Material {
+            material: match material.index() {
+                Some(index) => describe(material.name(), index),
+                None => String::from("default"),
+            },
+            reason: format!("has no {texture} texture"),
+        };
+
         let pbr_metallic_roughness = material.pbr_metallic_roughness();
         let base_color_texture = pbr_metallic_roughness
-            .base_color_texture()?
+            .base_color_texture()
+            .ok_or_else(|| missing("base color"))?
             .texture()
             .source()
             .index() as u32;
         let metallic_roughness_texture = pbr_metallic_roughness
-            .metallic_roughness_texture()?
+            .metallic_roughness_texture()
+            .ok_or_else(|| missing("metallic-roughness"))?
+            .texture()
+            .source()
+            .index() as u32;
+        let normal_texture = material
+            .normal_texture()
+            .ok_or_else(|| missing("normal"))?
+            .texture()
+            .source()
+            .index() as u32;
+        let emissive_texture = material
+            .emissive_texture()
+            .ok_or_else(|| missing("emissive"))?
             .texture()
             .source()
             .index() as u32;
-        let normal_texture = material.normal_texture()?.texture().source().index() as u32;
-        let emissive_texture = material.emissive_texture()?.texture().source().index() as u32;
 
         self.registry.push(Material {
             base_color_texture,
@@ -41,19 +65,20 @@ impl Materials {
             normal_texture,
             emissive_texture,
         });
-        Some(self.registry.len() as u32 - 1)
+        Ok(self.registry.len() as u32 - 1)
     }
 
     pub fn build(
         &self,
         context: &RenderContext,
-    ) -> encase::internal::Result<(wgpu::BindGroupLayout, wgpu::BindGroup)> {
+    ) -> Result<(wgpu::BindGroupLayout, wgpu::BindGroup), Error> {
         let device = context.device();
         let queue = context.queue();
 
         let mut wgsl_bytes = StorageBuffer::new(Vec::new());
         wgsl_bytes.write(&self.registry)?;
         let wgsl_bytes = wgsl_bytes.into_inner();
+        context.check_buffer_size("material", wgsl_bytes.len() as u64)?;
 
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
@@ -0,0 +1,107 @@
+use std::path::Path;
+
+use exr::prelude::{
+    AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, WritableImage,
+};
+use image::Rgba32FImage;
+
+use crate::{
+    config::{value_name, Aov, Config},
+    error::Error,
+    render::Aovs,
+};
+
+use super::Metadata;
+
+// Writes the requested passes to a file each next to the output, or to the layers of a single file
+pub fn save_aovs(aovs: &Aovs, config: &Config, metadata: &Metadata) -> Result<(), Error> {
+    if let Some(path) = &config.aov_output {
+        // The passes are grouped into layers by the prefixes of the channel names
+        let channels = config
+            .settings
+            .aovs
+            .iter()
+            .flat_map(|aov| {
+                let name = value_name(*aov);
+                channels(aovs, *aov)
+                    .into_iter()
+                    .map(move |(channel, samples)| (format!("{name}.{channel}"), samples))
+            })
+            .collect();
+        return write_exr(aovs, channels, Path::new(path), metadata);
+    }
+
+    let output = Path::new(&config.output);
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    for aov in &config.settings.aovs {
+        let path = output.with_file_name(format!("{stem}_{}.exr", value_name(*aov)));
+        let channels = channels(aovs, *aov)
+            .into_iter()
+            .map(|(channel, samples)| (channel.to_string(), samples))
+            .collect();
+        write_exr(aovs, channels, &path, metadata)?;
+    }
+    Ok(())
+}
+
+fn channels(aovs: &Aovs, aov: Aov) -> Vec<(&'static str, FlatSamples)> {
+    // The pixels outside of the region have no samples
+    let counts: Vec<_> = aovs
+        .albedo
+        .pixels()
+        .map(|pixel| pixel[3].max(1.0))
+        .collect();
+    let average = |image: &Rgba32FImage, channel: usize| {
+        let samples = image.pixels().zip(&counts);
+        FlatSamples::F32(
+            samples
+                .map(|(pixel, count)| pixel[channel] / count)
+                .collect(),
+        )
+    };
+    let ids =
+        |channel: usize| FlatSamples::U32(aovs.ids.pixels().map(|pixel| pixel[channel]).collect());
+
+    match aov {
+        Aov::Albedo => vec![
+            ("R", average(&aovs.albedo, 0)),
+            ("G", average(&aovs.albedo, 1)),
+            ("B", average(&aovs.albedo, 2)),
+        ],
+        Aov::Normal => vec![
+            ("X", average(&aovs.normal, 0)),
+            ("Y", average(&aovs.normal, 1)),
+            ("Z", average(&aovs.normal, 2)),
+        ],
+        Aov::Depth => vec![("Z", average(&aovs.normal, 3))],
+        Aov::Position => vec![
+            ("X", average(&aovs.position, 0)),
+            ("Y", average(&aovs.position, 1)),
+            ("Z", average(&aovs.position, 2)),
+        ],
+        Aov::Primitive => vec![("id", ids(0))],
+        Aov::Material => vec![("id", ids(1))],
+    }
+}
+
+fn write_exr(
+    aovs: &Aovs,
+    channels: Vec<(String, FlatSamples)>,
+    path: &Path,
+    metadata: &Metadata,
+) -> Result<(), Error> {
+    let channels = channels
+        .into_iter()
+        .map(|(name, samples)| AnyChannel::new(name.as_str(), samples))
+        .collect();
+    let layer = Layer::new(
+        (aovs.width() as usize, aovs.height() as usize),
+        metadata.layer_attributes(),
+        Encoding::FAST_LOSSLESS,
+        AnyChannels::sort(channels),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(Error::exr(&path.to_string_lossy()))
+}
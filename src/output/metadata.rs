@@ -0,0 +1,178 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs::File,
+    io::BufReader,
+};
+
+use exr::{
+    meta::{
+        attribute::{AttributeValue, Text},
+        MetaData,
+    },
+    prelude::LayerAttributes,
+};
+use png::Info;
+
+use crate::{
+    config::value_name,
+    error::Error,
+    render::{Checkpoint, RenderStats, Tile},
+    settings::RenderSettings,
+};
+
+use super::has_extension;
+
+// How an image was rendered, which is embedded as text into PNG and EXR files
+pub struct Metadata {
+    pub entries: Vec<(String, String)>,
+}
+
+impl Metadata {
+    pub fn render(scene_path: &str, settings: &RenderSettings, stats: &RenderStats) -> Self {
+        let mut metadata = Self::new();
+        metadata.push("Scene", scene_path);
+        metadata.push("Scene Hash", format!("{:016x}", stats.hash));
+        let camera = stats.scene.camera.as_deref().unwrap_or("default");
+        metadata.push("Camera", camera);
+        metadata.push("Resolution", settings.size);
+        if settings.region.is_some() {
+            let Tile {
+                x,
+                y,
+                width,
+                height,
+            } = Tile::region(settings);
+            metadata.push("Region", format!("{width}x{height}+{x}+{y}"));
+        }
+        metadata.push("Samples", stats.samples);
+        metadata.push("Depth", settings.depth);
+        metadata.push("Tone Mapping", value_name(settings.tone_mapping));
+        metadata.push("HDRI", &settings.hdri);
+        let render_time = stats.render_time.as_secs_f64();
+        metadata.push("Render Time", format!("{render_time:.3} s"));
+        let adapter = &stats.adapter;
+        metadata.push(
+            "Adapter",
+            format!("{} ({})", adapter.name, adapter.backend.to_str()),
+        );
+        metadata
+    }
+
+    // Checkpoints only know the samples and the hash of the scene they were rendered from
+    pub fn merge(settings: &RenderSettings, checkpoint: &Checkpoint) -> Self {
+        let mut metadata = Self::new();
+        metadata.push("Scene Hash", format!("{:016x}", checkpoint.hash));
+        metadata.push("Resolution", settings.size);
+        metadata.push("Samples", checkpoint.samples);
+        metadata.push("Tone Mapping", value_name(settings.tone_mapping));
+        metadata
+    }
+
+    // Reads all text of a PNG file or all text attributes of an EXR file
+    pub fn read(path: &str) -> Result<Self, Error> {
+        let entries = if has_extension(path, "png") {
+            let file = File::open(path).map_err(Error::io(path))?;
+            let reader = png::Decoder::new(BufReader::new(file))
+                .read_info()
+                .map_err(|error| Error::Metadata {
+                    path: path.to_string(),
+                    reason: error.to_string(),
+                })?;
+            png_text(reader.info())
+        } else if has_extension(path, "exr") {
+            let metadata = MetaData::read_from_file(path, false).map_err(Error::exr(path))?;
+            // The attributes are unordered, so they are sorted by their names
+            let mut entries: Vec<_> = metadata
+                .headers
+                .iter()
+                .flat_map(|header| &header.own_attributes.other)
+                .filter_map(|(name, value)| match value {
+                    AttributeValue::Text(text) => Some((
+                        String::from_utf8_lossy(name.bytes()).into_owned(),
+                        String::from_utf8_lossy(text.bytes()).into_owned(),
+                    )),
+                    _ => None,
+                })
+                .collect();
+            entries.sort();
+            entries
+        } else {
+            return Err(Error::Metadata {
+                path: path.to_string(),
+                reason: String::from("is not a PNG or EXR file"),
+            });
+        };
+        Ok(Self { entries })
+    }
+
+    // Text attributes are written as UTF-8, which the current OpenEXR specification requires
+    pub fn layer_attributes(&self) -> LayerAttributes {
+        let other: HashMap<_, _> = self
+            .entries
+            .iter()
+            .map(|(key, value)| {
+                (
+                    Text::from_slice_unchecked(key.as_bytes()),
+                    AttributeValue::Text(Text::from_slice_unchecked(value.as_bytes())),
+                )
+            })
+            .collect();
+        LayerAttributes {
+            other,
+            ..Default::default()
+        }
+    }
+
+    // Values outside of Latin-1 need an international text chunk
+    pub fn add_png_chunks<W: std::io::Write>(
+        &self,
+        encoder: &mut png::Encoder<W>,
+    ) -> Result<(), png::EncodingError> {
+        for (key, value) in &self.entries {
+            match value.chars().all(|char| (char as u32) < 0x100) {
+                true => encoder.add_text_chunk(key.clone(), value.clone())?,
+                false => encoder.add_itxt_chunk(key.clone(), value.clone())?,
+            }
+        }
+        Ok(())
+    }
+
+    fn new() -> Self {
+        let mut metadata = Self {
+            entries: Vec::new(),
+        };
+        metadata.push("Software", format!("prisma {}", env!("CARGO_PKG_VERSION")));
+        metadata
+    }
+
+    fn push(&mut self, key: &str, value: impl Display) {
+        self.entries.push((key.to_string(), value.to_string()));
+    }
+}
+
+impl Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.entries {
+            writeln!(f, "{key}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+// The order of the entries is only kept among the chunks of the same kind
+fn png_text(info: &Info) -> Vec<(String, String)> {
+    let latin1 = info
+        .uncompressed_latin1_text
+        .iter()
+        .map(|chunk| (chunk.keyword.clone(), chunk.text.clone()));
+    let compressed = info
+        .compressed_latin1_text
+        .iter()
+        .filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?)));
+    let utf8 = info
+        .utf8_text
+        .iter()
+        .filter_map(|chunk| Some((chunk.keyword.clone(), chunk.get_text().ok()?)));
+    latin1.chain(compressed).chain(utf8).collect()
+}
@@ -0,0 +1,167 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use exr::prelude::{Encoding, Layer, SpecificChannels, Vec2, WritableImage};
+use image::{error::EncodingError, DynamicImage, ImageError, ImageFormat, Rgba32FImage};
+
+use crate::{
+    config::{BitDepth, ColorSpace, Config},
+    error::Error,
+};
+
+mod aov;
+mod metadata;
+mod stats;
+
+pub use aov::save_aovs;
+pub use metadata::Metadata;
+pub use stats::save_stats;
+
+// Whether the file stores linear radiance instead of tone-mapped colors
+pub fn is_hdr(path: &str) -> bool {
+    has_extension(path, "exr") || has_extension(path, "hdr")
+}
+
+// Whether the file can hold the metadata of the render
+pub fn has_metadata(path: &str) -> bool {
+    has_extension(path, "png") || has_extension(path, "exr")
+}
+
+// The path the radiance is written to, which is the output itself if it is an HDR file
+pub fn hdr_path(config: &Config) -> Result<Option<&str>, Error> {
+    if is_hdr(&config.output) {
+        return Ok(Some(&config.output));
+    }
+    match &config.hdr_output {
+        Some(path) if !is_hdr(path) => Err(Error::Settings(format!(
+            "{path} is not an .exr or .hdr file"
+        ))),
+        path => Ok(path.as_deref()),
+    }
+}
+
+// TIFF files default to 16 bits per channel, which only they and PNG files can hold
+pub fn bit_depth(config: &Config) -> Result<BitDepth, Error> {
+    let path = &config.output;
+    let is_tiff = has_extension(path, "tif") || has_extension(path, "tiff");
+    match config.bit_depth {
+        Some(BitDepth::Sixteen) if !is_tiff && !has_extension(path, "png") => Err(Error::Settings(
+            format!("{path} can't hold 16 bits per channel, unlike PNG and TIFF files"),
+        )),
+        Some(bit_depth) => Ok(bit_depth),
+        None if is_tiff => Ok(BitDepth::Sixteen),
+        None => Ok(BitDepth::Eight),
+    }
+}
+
+// Divides the summed samples by their number, which also leaves an alpha of 1
+pub fn radiance(width: u32, height: u32, accumulation: Vec<f32>, samples: u32) -> Rgba32FImage {
+    let data = accumulation
+        .into_iter()
+        .map(|value| value / samples as f32)
+        .collect();
+    Rgba32FImage::from_raw(width, height, data).unwrap()
+}
+
+// Only OpenEXR files hold the metadata, since Radiance HDR files are written without a header
+pub fn save_hdr(image: &Rgba32FImage, path: &str, metadata: &Metadata) -> Result<(), Error> {
+    // Radiance HDR files have no alpha channel
+    if has_extension(path, "hdr") {
+        DynamicImage::ImageRgba32F(image.clone())
+            .into_rgb32f()
+            .save(path)
+            .map_err(Error::image(path))?;
+        return Ok(());
+    }
+
+    let size = (image.width() as usize, image.height() as usize);
+    let channels = SpecificChannels::rgba(|position: Vec2<usize>| {
+        let pixel = image.get_pixel(position.x() as u32, position.y() as u32);
+        (pixel[0], pixel[1], pixel[2], pixel[3])
+    });
+    let layer = Layer::new(
+        size,
+        metadata.layer_attributes(),
+        Encoding::default(),
+        channels,
+    );
+    exr::prelude::Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(Error::exr(path))
+}
+
+// Only PNG files are tagged with the color space and the metadata
+pub fn save(
+    image: &DynamicImage,
+    path: &str,
+    config: &Config,
+    metadata: &Metadata,
+) -> Result<(), Error> {
+    if !has_extension(path, "png") {
+        // The noisy output may be of a format holding only 8 bits per channel
+        let is_tiff = has_extension(path, "tif") || has_extension(path, "tiff");
+        let result = match image {
+            DynamicImage::ImageRgba16(_) if !is_tiff => image.to_rgba8().save(path),
+            image => image.save(path),
+        };
+        return result.map_err(Error::image(path));
+    }
+
+    let file = File::create(path).map_err(Error::io(path))?;
+    write_png(image, file, config, metadata).map_err(|error| Error::Image {
+        path: path.to_string(),
+        source: ImageError::Encoding(EncodingError::new(ImageFormat::Png.into(), error)),
+    })
+}
+
+fn write_png(
+    image: &DynamicImage,
+    file: File,
+    config: &Config,
+    metadata: &Metadata,
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(file), image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    // PNG stores 16-bit samples in big-endian order
+    let data = match image {
+        DynamicImage::ImageRgba16(image) => {
+            encoder.set_depth(png::BitDepth::Sixteen);
+            image.iter().flat_map(|value| value.to_be_bytes()).collect()
+        }
+        image => {
+            encoder.set_depth(png::BitDepth::Eight);
+            image.to_rgba8().into_raw()
+        }
+    };
+    // Decoders without cICP support still understand the sRGB chunk, and those without sRGB
+    // support the gamma of the sRGB curve
+    if let ColorSpace::Srgb = config.settings.color_space {
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        encoder.set_source_gamma(png::ScaledFloat::from_scaled(45455));
+    }
+    metadata.add_png_chunks(&mut encoder)?;
+
+    let mut writer = encoder.write_header()?;
+    writer.write_chunk(
+        png::chunk::ChunkType(*b"cICP"),
+        &cicp(config.settings.color_space),
+    )?;
+    writer.write_image_data(&data)?;
+    writer.finish()
+}
+
+fn has_extension(path: &str, extension: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .is_some_and(|path_extension| path_extension.eq_ignore_ascii_case(extension))
+}
+
+// The code points of ITU-T H.273 for the primaries, the transfer function, the matrix coefficients
+// and the full range flag
+fn cicp(color_space: ColorSpace) -> [u8; 4] {
+    match color_space {
+        ColorSpace::Srgb => [1, 13, 0, 1],
+        ColorSpace::DisplayP3 => [12, 13, 0, 1],
+        ColorSpace::Rec2020 => [9, 1, 0, 1],
+    }
+}
@@ -0,0 +1,87 @@
+use std::{fs, path::Path, time::Duration};
+
+use serde_json::json;
+
+use crate::{
+    config::{value_name, Config},
+    error::Error,
+    render::RenderStats,
+};
+
+// Writes the statistics of the render to a JSON file next to the output, with times in milliseconds
+pub fn save_stats(stats: &RenderStats, config: &Config) -> Result<(), Error> {
+    let output = Path::new(&config.output);
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let path = output.with_file_name(format!("{stem}_stats.json"));
+
+    let bvh = &stats.scene.bvh;
+    let buffer_sizes: serde_json::Map<_, _> = stats
+        .scene
+        .buffer_sizes
+        .iter()
+        .map(|(buffer, size)| (buffer.to_string(), json!(size)))
+        .collect();
+    let geometry_memory: serde_json::Map<_, _> = stats
+        .scene
+        .geometry_memory
+        .iter()
+        .map(|(layout, size)| (value_name(*layout), json!(size)))
+        .collect();
+    let sample_times: Vec<_> = stats.sample_times.iter().copied().map(millis).collect();
+    let render_seconds = stats.render_time.as_secs_f64();
+    let report = json!({
+        "adapter": {
+            "name": stats.adapter.name,
+            "backend": stats.adapter.backend.to_str(),
+            "device_type": format!("{:?}", stats.adapter.device_type),
+            "driver": stats.adapter.driver,
+            "driver_info": stats.adapter.driver_info,
+        },
+        "scene": {
+            "load_time_ms": millis(stats.load_time),
+            "triangles": stats.scene.triangles,
+            "vertices": stats.scene.vertices,
+            "geometry": {
+                "layout": value_name(stats.scene.geometry_layout),
+                "memory": geometry_memory,
+            },
+            "bvh": {
+                "build_time_ms": millis(bvh.build_time),
+                "nodes": bvh.nodes,
+                "leaves": bvh.leaves,
+                "depth": bvh.depth,
+                "max_leaf_triangles": bvh.max_leaf_triangles,
+                "sah_cost": bvh.sah_cost,
+            },
+            "texture_memory": stats.scene.texture_memory,
+            "texture_atlas": stats.scene.texture_atlas,
+            "buffer_sizes": buffer_sizes,
+        },
+        "render": {
+            "render_time_ms": millis(stats.render_time),
+            "samples": stats.samples,
+            "resumed_samples": stats.resumed_samples,
+            "tiles": stats.tiles,
+            "rays": stats.rays,
+            "rays_per_second": stats.rays.map(|rays| rays as f64 / render_seconds),
+            "sample_time_ms": {
+                "mean": mean(&sample_times),
+                "min": sample_times.iter().copied().reduce(f64::min),
+                "max": sample_times.iter().copied().reduce(f64::max),
+                "all": sample_times,
+            },
+        },
+    });
+
+    let text = serde_json::to_string_pretty(&report).unwrap() + "\n";
+    fs::write(&path, text).map_err(Error::io(&path.to_string_lossy()))
+}
+
+// Rounded to microseconds, which is finer than the timings are exact
+fn millis(duration: Duration) -> f64 {
+    duration.as_micros() as f64 / 1000.0
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
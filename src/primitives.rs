@@ -1,10 +1,12 @@
 use encase::StorageBuffer;
 use glam::{Vec2, Vec3};
-use gltf::{buffer::Data, Primitive};
+use gltf::{buffer::Data, Node, Primitive};
 
 use crate::{
-    core::{Triangle, Vertex},
-    render::RenderContext,
+    config::GeometryLayout,
+    core::{CompactVertex, Triangle, Vertex},
+    error::{describe, Error},
+    render::{RenderContext, StorageArray, StorageArrayLayout},
     scene::Transform,
 };
 
@@ -21,6 +23,10 @@ impl Primitives {
         Self::default()
     }
 
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
     pub fn vertex(&self, primitive: u32, vertex: u32) -> Vertex {
         self.vertices[(vertex + self.offsets[primitive as usize]) as usize]
     }
@@ -28,15 +34,49 @@ impl Primitives {
     pub fn add(
         &mut self,
         buffers: &[Data],
+        node: &Node,
         primitive: &Primitive,
         transform: &Transform,
         material_idx: u32,
-    ) -> Option<Vec<Triangle>> {
+    ) -> Result<Vec<Triangle>, Error> {
+        let invalid = |reason: &str| Error::Primitive {
+            node: describe(node.name(), node.index()),
+            primitive: primitive.index(),
+            reason: reason.to_string(),
+        };
+
         let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
-        let positions: Vec<_> = reader.read_positions()?.collect();
-        let normals: Vec<_> = reader.read_normals()?.collect();
-        let tex_coords: Vec<_> = reader.read_tex_coords(0)?.into_f32().collect();
+        let positions: Vec<_> = reader
+            .read_positions()
+            .ok_or_else(|| invalid("has no positions"))?
+            .collect();
+        let normals: Vec<_> = reader
+            .read_normals()
+            .ok_or_else(|| invalid("has no normals"))?
+            .collect();
+        let tex_coords: Vec<_> = reader
+            .read_tex_coords(0)
+            .ok_or_else(|| invalid("has no texture coordinates"))?
+            .into_f32()
+            .collect();
+        if normals.len() != positions.len() || tex_coords.len() != positions.len() {
+            return Err(invalid(
+                "has different numbers of positions, normals and texture coordinates",
+            ));
+        }
+        let indices: Vec<_> = reader
+            .read_indices()
+            .ok_or_else(|| invalid("has no indices"))?
+            .into_u32()
+            .collect();
+        if indices
+            .iter()
+            .any(|index| *index as usize >= positions.len())
+        {
+            return Err(invalid("has indices out of the range of its vertices"));
+        }
+
         let mut vertices = Vec::with_capacity(positions.len());
         for i in 0..positions.len() {
             vertices.push(Vertex {
@@ -56,7 +96,6 @@ impl Primitives {
         self.offsets.push(offset);
         self.material_indices.push(material_idx);
 
-        let indices: Vec<_> = reader.read_indices()?.into_u32().collect();
         let mut triangles = Vec::new();
         for i in 0..indices.len() / 3 {
             triangles.push(Triangle {
@@ -67,31 +106,31 @@ impl Primitives {
             });
         }
 
-        Some(triangles)
+        Ok(triangles)
     }
 
     pub fn build(
         &self,
         context: &RenderContext,
-    ) -> encase::internal::Result<(wgpu::BindGroupLayout, wgpu::BindGroup)> {
+        layout: GeometryLayout,
+    ) -> Result<(wgpu::BindGroupLayout, wgpu::BindGroup, StorageArrayLayout), Error> {
         let device = context.device();
         let queue = context.queue();
 
-        let mut wgsl_bytes = StorageBuffer::new(Vec::new());
-        wgsl_bytes.write(&self.vertices)?;
-        let wgsl_bytes = wgsl_bytes.into_inner();
-
-        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: wgsl_bytes.len() as u64,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
-            mapped_at_creation: false,
-        });
-        queue.write_buffer(&vertex_buffer, 0, &wgsl_bytes);
+        let vertices = match layout {
+            GeometryLayout::Standard => {
+                StorageArray::try_new(context, "vertex", "vertices", 0, &self.vertices)?
+            }
+            GeometryLayout::Compact => {
+                let vertices: Vec<_> = self.vertices.iter().map(CompactVertex::new).collect();
+                StorageArray::try_new(context, "vertex", "vertices", 0, &vertices)?
+            }
+        };
 
         let mut wgsl_bytes = StorageBuffer::new(Vec::new());
         wgsl_bytes.write(&self.offsets)?;
         let wgsl_bytes = wgsl_bytes.into_inner();
+        context.check_buffer_size("offset", wgsl_bytes.len() as u64)?;
 
         let offset_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
@@ -104,6 +143,7 @@ impl Primitives {
         let mut wgsl_bytes = StorageBuffer::new(Vec::new());
         wgsl_bytes.write(&self.transform_indices)?;
         let wgsl_bytes = wgsl_bytes.into_inner();
+        context.check_buffer_size("transform index", wgsl_bytes.len() as u64)?;
 
         let transform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
@@ -116,6 +156,7 @@ impl Primitives {
         let mut wgsl_bytes = StorageBuffer::new(Vec::new());
         wgsl_bytes.write(&self.material_indices)?;
         let wgsl_bytes = wgsl_bytes.into_inner();
+        context.check_buffer_size("material index", wgsl_bytes.len() as u64)?;
 
         let material_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
@@ -125,60 +166,30 @@ impl Primitives {
         });
         queue.write_buffer(&material_buffer, 0, &wgsl_bytes);
 
+        // The chunks of the vertices may exceed the bindings of the device
+        context.check_limits(0)?;
+        let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout_entries: Vec<_> = vertices
+            .layout_entries()
+            .chain([storage_entry(1), storage_entry(2), storage_entry(3)])
+            .collect();
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
+            entries: &layout_entries,
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: vertex_buffer.as_entire_binding(),
-                },
+        let entries: Vec<_> = vertices
+            .bind_group_entries()
+            .chain([
                 wgpu::BindGroupEntry {
                     binding: 1,
                     resource: offset_buffer.as_entire_binding(),
@@ -191,9 +202,14 @@ impl Primitives {
                     binding: 3,
                     resource: material_buffer.as_entire_binding(),
                 },
-            ],
+            ])
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &entries,
         });
 
-        Ok((bind_group_layout, bind_group))
+        Ok((bind_group_layout, bind_group, vertices.layout()))
     }
 }
@@ -0,0 +1,70 @@
+use image::{imageops, ImageBuffer, LumaA, Rgba32FImage};
+
+// The first hits of the camera rays, which are summed over the samples except for the IDs
+pub struct Aovs {
+    // The alpha channel counts the samples
+    pub albedo: Rgba32FImage,
+    // The alpha channel holds the depth
+    pub normal: Rgba32FImage,
+    pub position: Rgba32FImage,
+    // The indices of the primitives and the materials, offset by one
+    pub ids: ImageBuffer<LumaA<u32>, Vec<u32>>,
+}
+
+impl Aovs {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            albedo: Rgba32FImage::new(width, height),
+            normal: Rgba32FImage::new(width, height),
+            position: Rgba32FImage::new(width, height),
+            ids: ImageBuffer::new(width, height),
+        }
+    }
+
+    pub fn from_bytes(
+        width: u32,
+        height: u32,
+        albedo: &[u8],
+        normal: &[u8],
+        position: &[u8],
+        ids: &[u8],
+    ) -> Self {
+        let f32_image = |bytes: &[u8]| {
+            let data = bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap()))
+                .collect();
+            Rgba32FImage::from_raw(width, height, data).unwrap()
+        };
+        // Only the first two of the four channels hold IDs
+        let ids = ids
+            .chunks_exact(16)
+            .flat_map(|pixel| pixel[..8].chunks_exact(4))
+            .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        Self {
+            albedo: f32_image(albedo),
+            normal: f32_image(normal),
+            position: f32_image(position),
+            ids: ImageBuffer::from_raw(width, height, ids).unwrap(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.albedo.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.albedo.height()
+    }
+
+    // Copies the AOVs of a tile into place
+    pub fn replace(&mut self, aovs: &Aovs, x: u32, y: u32) {
+        let [x, y] = [x as i64, y as i64];
+        imageops::replace(&mut self.albedo, &aovs.albedo, x, y);
+        imageops::replace(&mut self.normal, &aovs.normal, x, y);
+        imageops::replace(&mut self.position, &aovs.position, x, y);
+        imageops::replace(&mut self.ids, &aovs.ids, x, y);
+    }
+}
@@ -0,0 +1,406 @@
+use std::{
+    fs::{self, File},
+    hash::Hasher,
+    io::{self, BufReader, BufWriter, Read, Write},
+    slice,
+};
+
+use crate::{error::Error, settings::RenderSettings};
+
+use super::{RenderContext, Tile};
+
+const MAGIC: &[u8; 8] = b"PRISMACK";
+const VERSION: u32 = 2;
+// The magic, the version, five sizes and counts, and the hash
+const HEADER_LEN: u64 = 8 + 4 + 5 * 4 + 8;
+
+pub struct Checkpoint {
+    pub width: u32,
+    pub height: u32,
+    pub samples: u32,
+    pub sample_offset: u32,
+    pub sample_index: u32,
+    pub hash: u64,
+    pub data: Vec<f32>,
+}
+
+impl Checkpoint {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let file = File::open(path).map_err(Error::io(path))?;
+        let file_len = file.metadata().map_err(Error::io(path))?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).map_err(Error::io(path))?;
+        if &magic != MAGIC {
+            return Err(Error::Checkpoint(format!(
+                "{path} is not a checkpoint file"
+            )));
+        }
+        let version = read_u32(&mut reader).map_err(Error::io(path))?;
+        if version != VERSION {
+            return Err(Error::Checkpoint(format!(
+                "unsupported checkpoint version {version} in {path}"
+            )));
+        }
+
+        let mut read_header = || -> io::Result<_> {
+            let width = read_u32(&mut reader)?;
+            let height = read_u32(&mut reader)?;
+            let samples = read_u32(&mut reader)?;
+            let sample_offset = read_u32(&mut reader)?;
+            let sample_index = read_u32(&mut reader)?;
+            let mut hash = [0; 8];
+            reader.read_exact(&mut hash)?;
+            Ok((width, height, samples, sample_offset, sample_index, hash))
+        };
+        let (width, height, samples, sample_offset, sample_index, hash) =
+            read_header().map_err(Error::io(path))?;
+        let hash = u64::from_le_bytes(hash);
+        if width == 0 || height == 0 {
+            return Err(Error::Checkpoint(format!("{path} has an empty size")));
+        }
+
+        // The size comes from the file, so it is checked against what is left of it before
+        // anything is allocated
+        let data_len = (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|texels| texels.checked_mul(16))
+            .filter(|&len| Some(len) == file_len.checked_sub(HEADER_LEN))
+            .ok_or_else(|| {
+                Error::Checkpoint(format!(
+                    "{path} is truncated or doesn't match its size of {width}x{height}"
+                ))
+            })?;
+        let mut bytes = vec![0; data_len as usize];
+        reader.read_exact(&mut bytes).map_err(Error::io(path))?;
+        let data = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            samples,
+            sample_offset,
+            sample_index,
+            hash,
+            data,
+        })
+    }
+
+    // Whether the render of the tile can be resumed from the checkpoint
+    pub fn check(&self, hash: u64, tile: &Tile) -> Result<(), Error> {
+        if self.hash != hash {
+            return Err(Error::Checkpoint(String::from(
+                "the scene or settings have changed since the checkpoint was saved",
+            )));
+        }
+        if self.width != tile.width || self.height != tile.height {
+            return Err(Error::Checkpoint(String::from(
+                "the checkpoint does not match the size of the rendered region",
+            )));
+        }
+        Ok(())
+    }
+
+    // Sums the accumulations of renders with disjoint ranges of sample indices
+    pub fn merge(checkpoints: Vec<Self>) -> Result<Self, Error> {
+        let mut checkpoints = checkpoints;
+        checkpoints.sort_by_key(|checkpoint| checkpoint.sample_offset);
+
+        let mut checkpoints = checkpoints.into_iter();
+        let mut merged = checkpoints
+            .next()
+            .ok_or_else(|| Error::Checkpoint(String::from("no checkpoints to be merged")))?;
+        for checkpoint in checkpoints {
+            if checkpoint.hash != merged.hash {
+                return Err(Error::Checkpoint(String::from(
+                    "the checkpoints were rendered from different scenes or settings",
+                )));
+            }
+            if checkpoint.width != merged.width || checkpoint.height != merged.height {
+                return Err(Error::Checkpoint(String::from(
+                    "the checkpoints have different sizes",
+                )));
+            }
+            if checkpoint.sample_offset < merged.sample_index {
+                return Err(Error::Checkpoint(format!(
+                    "the sample indices {}..{} overlap with a previous checkpoint",
+                    checkpoint.sample_offset, checkpoint.sample_index
+                )));
+            }
+
+            for (sum, value) in merged.data.iter_mut().zip(&checkpoint.data) {
+                *sum += value;
+            }
+            merged.samples += checkpoint.samples;
+            merged.sample_index = checkpoint.sample_index;
+        }
+
+        Ok(merged)
+    }
+
+    pub fn create_texture(&self, context: &RenderContext) -> wgpu::Texture {
+        let texture = context.device().create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        self.write_texture(context, &texture);
+        texture
+    }
+
+    pub fn write_texture(&self, context: &RenderContext, texture: &wgpu::Texture) {
+        let data = &self.data;
+        context.queue().write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 4) },
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * 16),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Error> {
+        // Write to a temporary file first so that an interruption never leaves a torn checkpoint
+        let tmp_path = format!("{path}.tmp");
+        self.write(&tmp_path).map_err(Error::io(&tmp_path))?;
+        fs::rename(&tmp_path, path).map_err(Error::io(path))?;
+        Ok(())
+    }
+
+    fn write(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        for value in [
+            VERSION,
+            self.width,
+            self.height,
+            self.samples,
+            self.sample_offset,
+            self.sample_index,
+        ] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.write_all(&self.hash.to_le_bytes())?;
+        for value in &self.data {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        writer.flush()
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+// FNV-1a is used instead of `DefaultHasher` since the hash has to be stable across builds
+pub struct InputHasher(u64);
+
+impl InputHasher {
+    pub fn new(scene_path: &str, settings: &RenderSettings) -> Result<Self, Error> {
+        let mut hasher = Self(0xCBF2_9CE4_8422_2325);
+        hasher.write(&fs::read(scene_path).map_err(Error::io(scene_path))?);
+        hasher.write(&fs::read(&settings.hdri).map_err(Error::io(&settings.hdri))?);
+        hasher.write(settings.camera.as_deref().unwrap_or_default().as_bytes());
+        let region = Tile::region(settings);
+        for value in [
+            settings.size.width,
+            settings.size.height,
+            settings.depth,
+            region.x,
+            region.y,
+            region.width,
+            region.height,
+        ] {
+            hasher.write(&value.to_le_bytes());
+        }
+        Ok(hasher)
+    }
+}
+
+impl Hasher for InputHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01B3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    fn checkpoint(sample_offset: u32, samples: u32) -> Checkpoint {
+        Checkpoint {
+            width: 2,
+            height: 1,
+            samples,
+            sample_offset,
+            sample_index: sample_offset + samples,
+            hash: 42,
+            data: (0..8).map(|i| i as f32).collect(),
+        }
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = env::temp_dir().join(format!("prisma-{}-{name}.ckpt", std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    // Loads the bytes of a checkpoint whose header was changed
+    fn load_modified(name: &str, modify: impl FnOnce(&mut Vec<u8>)) -> Result<Checkpoint, Error> {
+        let path = temp_path(name);
+        checkpoint(0, 4).save(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        modify(&mut bytes);
+        fs::write(&path, bytes).unwrap();
+        let result = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn message(result: Result<Checkpoint, Error>) -> String {
+        match result {
+            Err(Error::Checkpoint(message)) => message,
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(_) => panic!("the checkpoint was accepted"),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let loaded = load_modified("round-trip", |_| {}).unwrap();
+        let saved = checkpoint(0, 4);
+        assert_eq!((loaded.width, loaded.height), (2, 1));
+        assert_eq!(loaded.samples, saved.samples);
+        assert_eq!(loaded.sample_index, saved.sample_index);
+        assert_eq!(loaded.hash, saved.hash);
+        assert_eq!(loaded.data, saved.data);
+    }
+
+    #[test]
+    fn wrong_magic() {
+        let result = load_modified("magic", |bytes| bytes[0] = b'X');
+        assert!(message(result).contains("not a checkpoint file"));
+    }
+
+    #[test]
+    fn wrong_version() {
+        let result = load_modified("version", |bytes| bytes[8] = 1);
+        assert!(message(result).contains("unsupported checkpoint version 1"));
+    }
+
+    #[test]
+    fn truncated() {
+        let result = load_modified("truncated", |bytes| bytes.truncate(bytes.len() - 4));
+        assert!(message(result).contains("truncated"));
+    }
+
+    #[test]
+    fn size_larger_than_file() {
+        // A width of 2^31 would allocate 32 GiB for a single row
+        let result = load_modified("size", |bytes| bytes[15] = 0x80);
+        assert!(message(result).contains("doesn't match its size"));
+    }
+
+    #[test]
+    fn empty_size() {
+        let result = load_modified("empty", |bytes| bytes[16..20].fill(0));
+        assert!(message(result).contains("empty size"));
+    }
+
+    #[test]
+    fn hash_mismatch() {
+        let tile = Tile {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        let loaded = load_modified("hash", |_| {}).unwrap();
+        assert!(loaded.check(42, &tile).is_ok());
+        let Err(Error::Checkpoint(message)) = loaded.check(43, &tile) else {
+            panic!("the hash was accepted");
+        };
+        assert!(message.contains("have changed"));
+    }
+
+    fn merge_message(checkpoints: Vec<Checkpoint>) -> String {
+        match Checkpoint::merge(checkpoints) {
+            Err(Error::Checkpoint(message)) => message,
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(_) => panic!("the checkpoints were merged"),
+        }
+    }
+
+    #[test]
+    fn merge_out_of_order() {
+        let merged =
+            Checkpoint::merge(vec![checkpoint(8, 2), checkpoint(0, 4), checkpoint(4, 4)]).unwrap();
+        assert_eq!(merged.samples, 10);
+        assert_eq!(merged.sample_offset, 0);
+        assert_eq!(merged.sample_index, 10);
+        let expected: Vec<_> = (0..8).map(|i| i as f32 * 3.0).collect();
+        assert_eq!(merged.data, expected);
+    }
+
+    #[test]
+    fn merge_overlapping() {
+        let message = merge_message(vec![checkpoint(0, 4), checkpoint(2, 4)]);
+        assert!(message.contains("2..6 overlap"));
+    }
+
+    #[test]
+    fn merge_different_hashes() {
+        let mut other = checkpoint(4, 4);
+        other.hash = 43;
+        let message = merge_message(vec![checkpoint(0, 4), other]);
+        assert!(message.contains("different scenes or settings"));
+    }
+
+    #[test]
+    fn merge_different_sizes() {
+        let mut other = checkpoint(4, 4);
+        (other.width, other.height) = (1, 2);
+        let message = merge_message(vec![checkpoint(0, 4), other]);
+        assert!(message.contains("different sizes"));
+    }
+
+    #[test]
+    fn merge_nothing() {
+        assert!(merge_message(Vec::new()).contains("no checkpoints"));
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    hash::Hasher,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use image::{imageops, DynamicImage, ImageBuffer, Pixel, Rgba, Rgba32FImage};
+
+use crate::{
+    config::{BitDepth, GeometryLayout, RegionOutput},
+    core,
+    error::Error,
+    output,
+    scene::{BvhStats, Scene},
+    settings::RenderSettings,
+    textures,
+};
+
+use super::{
+    Aovs, BindGroupLayoutSet, BindGroupSet, Checkpoint, InputHasher, PostProcessor, RenderContext,
+    Renderer, Tile,
+};
+
+pub struct RenderOutput {
+    // The linear radiance, i.e. the accumulated samples divided by their number
+    pub hdr_image: Rgba32FImage,
+    // The post-processed image with the bit depth of the settings
+    pub image: DynamicImage,
+    // The post-processed image without denoising, if `keep_noisy` is set
+    pub noisy_image: Option<DynamicImage>,
+    // The auxiliary passes, if any are requested
+    pub aovs: Option<Aovs>,
+    pub stats: RenderStats,
+}
+
+pub struct RenderStats {
+    // The samples per pixel, including those resumed from the checkpoint
+    pub samples: u32,
+    pub resumed_samples: Option<u32>,
+    pub tiles: usize,
+    // The exposure metered by `auto_exposure`
+    pub exposure: Option<f32>,
+    pub load_time: Duration,
+    pub render_time: Duration,
+    // The hash of the scene and the settings, which checkpoints are matched by
+    pub hash: u64,
+    pub adapter: wgpu::AdapterInfo,
+    pub scene: SceneStats,
+    // The times and the rays of the samples, which are only measured with `collect_stats`
+    pub sample_times: Vec<Duration>,
+    pub rays: Option<u64>,
+}
+
+pub struct SceneStats {
+    // The node of the camera rendered from, which is unset for the default camera
+    pub camera: Option<String>,
+    pub triangles: usize,
+    pub vertices: usize,
+    pub bvh: BvhStats,
+    // The layout of the geometry, and the bytes it takes in each layout for comparison
+    pub geometry_layout: GeometryLayout,
+    pub geometry_memory: Vec<(GeometryLayout, u64)>,
+    // The bytes of the textures and of the storage buffers on the device
+    pub texture_memory: u64,
+    // Whether the textures are packed into an atlas
+    pub texture_atlas: bool,
+    pub buffer_sizes: Vec<(&'static str, u64)>,
+}
+
+// Renders the glTF scene at the path into the HDR and the post-processed image
+pub fn render(scene_path: &str, settings: &RenderSettings) -> Result<RenderOutput, Error> {
+    let context = pollster::block_on(RenderContext::try_new(settings))?;
+    let settings = &supported_settings(&context, settings);
+    check_tiles(&context, settings)?;
+    let start = Instant::now();
+    let (bind_group_layout_set, bind_group_set, hash, scene) =
+        build_scene(&context, scene_path, settings)?;
+    let load_time = start.elapsed();
+
+    let start = Instant::now();
+    let mut renderer = Renderer::new(&context, settings, bind_group_layout_set)?;
+    let mut resumed_samples = None;
+    // Resuming requires a checkpoint to be set
+    if let Some(checkpoint_path) = settings.checkpoint.as_deref() {
+        if settings.resume && Path::new(checkpoint_path).exists() {
+            let checkpoint = Checkpoint::load(checkpoint_path)?;
+            renderer.resume(&checkpoint, hash)?;
+            resumed_samples = Some(checkpoint.samples);
+        }
+    }
+
+    let post_processor = PostProcessor::new(&context, settings)?;
+    let tiles = renderer.tiles().to_vec();
+    let window = Tile::window(settings);
+    let mut image = output_image_ldr(settings);
+    let mut noisy_image = settings.keep_noisy.then(|| output_image_ldr(settings));
+    let mut hdr_image = output_image(settings, Rgba([0.0, 0.0, 0.0, 1.0]));
+    let mut aovs = (!settings.aovs.is_empty()).then(|| Aovs::new(window.width, window.height));
+    for (i, tile) in tiles.iter().enumerate() {
+        // The first tile may continue from a resumed checkpoint
+        if i > 0 {
+            renderer.clear();
+        }
+        renderer.render(&bind_group_set, tile, hash)?;
+        let samples = renderer.completed_samples();
+
+        let accumulation = renderer.read_accumulation(tile)?;
+        let [x, y] = [(tile.x - window.x) as i64, (tile.y - window.y) as i64];
+        imageops::replace(
+            &mut hdr_image,
+            &output::radiance(tile.width, tile.height, accumulation, samples),
+            x,
+            y,
+        );
+        if let Some(aovs) = &mut aovs {
+            // AOVs are always present if requested
+            let tile_aovs = renderer.read_aovs(tile)?.unwrap();
+            aovs.replace(&tile_aovs, tile.x - window.x, tile.y - window.y);
+        }
+
+        let accumulation = renderer.render_target();
+        post_process_tile(
+            &post_processor,
+            accumulation,
+            samples,
+            renderer.aov_targets(),
+            &mut image,
+            tile,
+            &window,
+        )?;
+        // The noisy image skips the denoiser, which needs the AOV targets
+        if let Some(noisy_image) = &mut noisy_image {
+            post_process_tile(
+                &post_processor,
+                accumulation,
+                samples,
+                None,
+                noisy_image,
+                tile,
+                &window,
+            )?;
+        }
+    }
+
+    let stats = RenderStats {
+        samples: renderer.completed_samples(),
+        resumed_samples,
+        tiles: tiles.len(),
+        exposure: post_processor.metered_exposure(),
+        load_time,
+        render_time: start.elapsed(),
+        hash,
+        adapter: context.adapter_info().clone(),
+        scene,
+        sample_times: renderer.sample_times().to_vec(),
+        rays: match settings.collect_stats {
+            true => Some(renderer.read_ray_count()?),
+            false => None,
+        },
+    };
+    Ok(RenderOutput {
+        hdr_image,
+        image,
+        noisy_image,
+        aovs,
+        stats,
+    })
+}
+
+fn build_scene(
+    context: &RenderContext,
+    scene_path: &str,
+    settings: &RenderSettings,
+) -> Result<(BindGroupLayoutSet, BindGroupSet, u64, SceneStats), Error> {
+    let (document, buffers, images) = gltf::import(scene_path).map_err(|source| Error::Gltf {
+        path: scene_path.to_string(),
+        source,
+    })?;
+
+    let mut hasher = InputHasher::new(scene_path, settings)?;
+    for buffer in &buffers {
+        hasher.write(buffer);
+    }
+    for image in &images {
+        hasher.write(&image.pixels);
+    }
+
+    let mut scene = Scene::new(context);
+    scene.load(settings, &document, &buffers, &images)?;
+
+    let hdri = scene.textures.load_texture_hdr(&settings.hdri)?;
+    scene.set_hdri(hdri);
+
+    let triangles = scene.triangle_count();
+    let vertices = scene.primitives.vertex_count();
+    let layout = settings.geometry_layout;
+    let (scene_bind_group_layout, scene_bind_group, mut arrays, bvh) =
+        scene.build(context, layout)?;
+    let (primitive_bind_group_layout, primitive_bind_group, vertex_array) =
+        scene.primitives.build(context, layout)?;
+    arrays.push(vertex_array);
+    let (material_bind_group_layout, material_bind_group) = scene.materials.build(context)?;
+    let texture_atlas = textures::uses_atlas(context, settings);
+    let (texture_bind_group_layout, texture_bind_group) = scene.textures.build(texture_atlas)?;
+
+    let bind_group_layout_set = BindGroupLayoutSet {
+        scene: scene_bind_group_layout,
+        primitive: primitive_bind_group_layout,
+        material: material_bind_group_layout,
+        texture: texture_bind_group_layout,
+        arrays,
+    };
+    let bind_group_set = BindGroupSet {
+        scene: scene_bind_group,
+        primitive: primitive_bind_group,
+        material: material_bind_group,
+        texture: texture_bind_group,
+    };
+    let stats = SceneStats {
+        camera: scene.camera_node().map(String::from),
+        triangles,
+        vertices,
+        bvh,
+        geometry_layout: layout,
+        geometry_memory: GeometryLayout::value_variants()
+            .iter()
+            .map(|layout| (*layout, core::geometry_memory(*layout, triangles, vertices)))
+            .collect(),
+        texture_memory: scene.textures.memory(),
+        texture_atlas,
+        buffer_sizes: context.buffer_sizes(),
+    };
+    Ok((bind_group_layout_set, bind_group_set, hasher.finish(), stats))
+}
+
+// Turns off the AOVs and the denoiser on adapters which can't bind their targets
+fn supported_settings(context: &RenderContext, settings: &RenderSettings) -> RenderSettings {
+    let mut settings = settings.clone();
+    if settings.renders_aovs() && !context.supports_aovs() {
+        eprintln!(
+            "Warning: {} binds too few storage textures per shader stage, so the AOVs and the \
+             denoiser are turned off",
+            context.adapter_info().name
+        );
+        settings.aovs.clear();
+        settings.denoise = false;
+    }
+    settings
+}
+
+// Checkpoints and auto exposure cover a single tile, which is checked before the scene is loaded
+fn check_tiles(context: &RenderContext, settings: &RenderSettings) -> Result<(), Error> {
+    let tiles = Tile::split(&Tile::region(settings), &Tile::max_size(context, settings)).len();
+    if tiles == 1 {
+        return Ok(());
+    }
+    let feature = if settings.checkpoint.is_some() {
+        "checkpoints are"
+    } else if settings.auto_exposure {
+        "auto exposure is"
+    } else {
+        return Ok(());
+    };
+    Err(Error::Settings(format!(
+        "{feature} not supported for tiled renders, and the image is split into {tiles} tiles"
+    )))
+}
+
+// Post-processes the accumulation of the tile into its place in the image
+fn post_process_tile(
+    post_processor: &PostProcessor,
+    accumulation: &wgpu::Texture,
+    samples: u32,
+    aov_targets: Option<&[wgpu::Texture; 4]>,
+    image: &mut DynamicImage,
+    tile: &Tile,
+    window: &Tile,
+) -> Result<(), Error> {
+    post_processor.post_process(accumulation, samples, tile, aov_targets)?;
+    let result = pollster::block_on(post_processor.retrieve_result(tile))?;
+    let [x, y] = [(tile.x - window.x) as i64, (tile.y - window.y) as i64];
+    imageops::replace(image, &result, x, y);
+    Ok(())
+}
+
+// The image of the post-processed output, with 8 or 16 bits per channel
+fn output_image_ldr(settings: &RenderSettings) -> DynamicImage {
+    match settings.bit_depth {
+        BitDepth::Eight => output_image(settings, Rgba([0, 0, 0, u8::MAX])).into(),
+        BitDepth::Sixteen => output_image(settings, Rgba([0, 0, 0, u16::MAX])).into(),
+    }
+}
+
+// The pixels outside of the region are filled with black or left transparent
+fn output_image<P: Pixel>(settings: &RenderSettings, black: P) -> ImageBuffer<P, Vec<P::Subpixel>> {
+    let window = Tile::window(settings);
+    match settings.region_output {
+        RegionOutput::Black => ImageBuffer::from_pixel(window.width, window.height, black),
+        RegionOutput::Crop | RegionOutput::Transparent => {
+            ImageBuffer::new(window.width, window.height)
+        }
+    }
+}
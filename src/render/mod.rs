@@ -1,7 +1,19 @@
+mod aov;
+mod checkpoint;
+mod frame;
 mod post_processor;
+mod push_constants;
 mod render_context;
 mod renderer;
+mod storage_array;
+mod tile;
 
-pub use post_processor::PostProcessor;
+pub use aov::Aovs;
+pub use checkpoint::{Checkpoint, InputHasher};
+pub use frame::{render, RenderOutput, RenderStats, SceneStats};
+pub use post_processor::{PostProcessor, PostStage};
+pub use push_constants::PushConstants;
 pub use render_context::RenderContext;
 pub use renderer::{BindGroupLayoutSet, BindGroupSet, Renderer};
+pub use storage_array::{StorageArray, StorageArrayLayout};
+pub use tile::Tile;
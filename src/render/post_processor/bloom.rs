@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+
+use crate::{config::Size, error::Error, settings::RenderSettings};
+
+use crate::render::{PushConstants, RenderContext, Tile};
+
+use super::PostStage;
+
+pub struct Bloom<'a> {
+    context: &'a RenderContext,
+    levels: u32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    push_constants: PushConstants,
+    prefilter_pipeline: wgpu::ComputePipeline,
+    downsample_pipeline: wgpu::ComputePipeline,
+    upsample_pipeline: wgpu::ComputePipeline,
+    glare_pipeline: Option<wgpu::ComputePipeline>,
+    composite_pipeline: wgpu::ComputePipeline,
+    // The levels of the mip chain, each half the size of the previous one
+    down_textures: Vec<wgpu::Texture>,
+    // The levels with the coarser ones added, except for the coarsest one, which is kept as it is
+    up_textures: Vec<wgpu::Texture>,
+    glare_texture: Option<wgpu::Texture>,
+}
+
+impl<'a> Bloom<'a> {
+    pub fn new(context: &'a RenderContext, settings: &RenderSettings) -> Result<Self, Error> {
+        let device = context.device();
+
+        // The bloom is applied to a single tile at a time
+        let size = Tile::max_size(context, settings);
+
+        // Every level doubles the radius, until the coarsest level is a single pixel wide
+        let levels = ((settings.bloom_radius.max(2) - 1).ilog2() + 1)
+            .min(size.width.min(size.height).max(2).ilog2());
+
+        let push_constants = PushConstants::new(context, 16);
+
+        // Three textures are read, and the unused ones are bound to the source
+        let entries: Vec<_> = (0..4)
+            .map(|i| wgpu::BindGroupLayoutEntry {
+                binding: i,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: if i < 3 {
+                        wgpu::StorageTextureAccess::ReadOnly
+                    } else {
+                        wgpu::StorageTextureAccess::WriteOnly
+                    },
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            })
+            .chain(push_constants.layout_entry())
+            .collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &push_constants.ranges(),
+        });
+
+        // Odd numbers of aperture blades cause twice as many streaks as even ones
+        let streaks = settings
+            .glare_blades
+            .map(|blades| if blades % 2 == 0 { blades } else { blades * 2 });
+
+        let mut constants = HashMap::new();
+        constants.insert(String::from("THRESHOLD"), settings.bloom_threshold as f64);
+        constants.insert(String::from("INTENSITY"), settings.bloom_intensity as f64);
+        constants.insert(String::from("LEVELS"), levels as f64);
+        if let Some(streaks) = streaks {
+            constants.insert(String::from("STREAKS"), streaks as f64);
+            // The glare is computed at half resolution
+            let length = (settings.bloom_radius / 2).max(1);
+            constants.insert(String::from("STREAK_LENGTH"), length as f64);
+        }
+
+        let [prefilter, downsample, upsample, glare, composite] =
+            context.validate("bloom", |device| {
+                let shader_module = device.create_shader_module(push_constants.shader(
+                    wgpu::include_wgsl!("../../../shaders-generated/bloom.wgsl"),
+                ));
+                ["prefilter", "downsample", "upsample", "glare", "composite"].map(|entry_point| {
+                    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                        label: None,
+                        layout: Some(&pipeline_layout),
+                        module: &shader_module,
+                        entry_point,
+                        compilation_options: wgpu::PipelineCompilationOptions {
+                            constants: &constants,
+                            zero_initialize_workgroup_memory: true,
+                            vertex_pulling_transform: false,
+                        },
+                        cache: None,
+                    })
+                })
+            })?;
+
+        let create_texture = |Size { width, height }| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::STORAGE_BINDING,
+                view_formats: &[],
+            })
+        };
+
+        let down_textures: Vec<_> = (0..levels)
+            .map(|level| create_texture(level_size(size, level)))
+            .collect();
+        let up_textures = (0..levels - 1)
+            .map(|level| create_texture(level_size(size, level)))
+            .collect();
+
+        Ok(Self {
+            context,
+            levels,
+            bind_group_layout,
+            push_constants,
+            prefilter_pipeline: prefilter,
+            downsample_pipeline: downsample,
+            upsample_pipeline: upsample,
+            glare_pipeline: streaks.map(|_| glare),
+            composite_pipeline: composite,
+            down_textures,
+            up_textures,
+            glare_texture: streaks.map(|_| create_texture(level_size(size, 0))),
+        })
+    }
+
+    fn dispatch(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::ComputePipeline,
+        textures: [&wgpu::Texture; 4],
+        src_size: Size,
+        dst_size: Size,
+    ) {
+        let device = self.context.device();
+
+        let constants: Vec<u8> = [
+            src_size.width,
+            src_size.height,
+            dst_size.width,
+            dst_size.height,
+        ]
+        .iter()
+        .flat_map(|constant| constant.to_ne_bytes())
+        .collect();
+        let constants_buffer = self.push_constants.create_buffer(device, &constants);
+
+        let views =
+            textures.map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        let entries: Vec<_> = views
+            .iter()
+            .enumerate()
+            .map(|(i, view)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .chain(PushConstants::bind_group_entry(&constants_buffer))
+            .collect();
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        });
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(pipeline);
+        compute_pass.set_bind_group(0, &bind_group, &[]);
+        self.push_constants.set(&mut compute_pass, &constants);
+        compute_pass.dispatch_workgroups(
+            dst_size.width.div_ceil(16),
+            dst_size.height.div_ceil(16),
+            1,
+        );
+    }
+}
+
+impl PostStage for Bloom<'_> {
+    fn apply(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        _aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error> {
+        let device = self.context.device();
+        let queue = self.context.queue();
+
+        let tile_size = Size::new(tile.width, tile.height);
+        let levels = self.levels as usize;
+        let down = &self.down_textures;
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        self.dispatch(
+            &mut encoder,
+            &self.prefilter_pipeline,
+            [src_texture, src_texture, src_texture, &down[0]],
+            tile_size,
+            level_size(tile_size, 0),
+        );
+        for level in 1..levels {
+            let src_size = level_size(tile_size, level as u32 - 1);
+            self.dispatch(
+                &mut encoder,
+                &self.downsample_pipeline,
+                [
+                    &down[level - 1],
+                    &down[level - 1],
+                    &down[level - 1],
+                    &down[level],
+                ],
+                src_size,
+                level_size(tile_size, level as u32),
+            );
+        }
+        if let (Some(pipeline), Some(glare)) = (&self.glare_pipeline, &self.glare_texture) {
+            let size = level_size(tile_size, 0);
+            self.dispatch(
+                &mut encoder,
+                pipeline,
+                [&down[0], &down[0], &down[0], glare],
+                size,
+                size,
+            );
+        }
+        for level in (0..levels - 1).rev() {
+            let coarser = self.up_textures.get(level + 1).unwrap_or(&down[levels - 1]);
+            self.dispatch(
+                &mut encoder,
+                &self.upsample_pipeline,
+                [&down[level], coarser, coarser, &self.up_textures[level]],
+                level_size(tile_size, level as u32 + 1),
+                level_size(tile_size, level as u32),
+            );
+        }
+        let bloom = self.up_textures.first().unwrap_or(&down[0]);
+        let glare = self.glare_texture.as_ref().unwrap_or(src_texture);
+        self.dispatch(
+            &mut encoder,
+            &self.composite_pipeline,
+            [src_texture, bloom, glare, dst_texture],
+            level_size(tile_size, 0),
+            tile_size,
+        );
+
+        queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+}
+
+// The size of a level of the mip chain, of which the first one is half the size of the image
+fn level_size(size: Size, level: u32) -> Size {
+    let divisor = 2 << level;
+    Size::new(size.width.div_ceil(divisor), size.height.div_ceil(divisor))
+}
@@ -0,0 +1,190 @@
+use std::{cell::Cell, collections::HashMap};
+
+use crate::{config::Size, error::Error, settings::RenderSettings};
+
+use crate::render::{PushConstants, RenderContext, Tile};
+
+use super::PostStage;
+
+pub struct Denoiser<'a> {
+    context: &'a RenderContext,
+    iterations: u32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    push_constants: PushConstants,
+    pipeline: wgpu::ComputePipeline,
+    // The iterations alternate between the textures until the last one writes the destination
+    textures: [wgpu::Texture; 2],
+    // The samples of the radiance, which include those resumed from a checkpoint unlike the AOVs
+    samples: Cell<u32>,
+}
+
+impl<'a> Denoiser<'a> {
+    pub fn new(context: &'a RenderContext, settings: &RenderSettings) -> Result<Self, Error> {
+        let device = context.device();
+
+        let Size { width, height } = Tile::max_size(context, settings);
+        let iterations = settings.denoise_iterations.max(1);
+        let push_constants = PushConstants::new(context, 16);
+
+        // The radiance and the albedo, normal and position AOVs are followed by the destination
+        let entries: Vec<_> = (0..5)
+            .map(|i| wgpu::BindGroupLayoutEntry {
+                binding: i,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: if i < 4 {
+                        wgpu::StorageTextureAccess::ReadOnly
+                    } else {
+                        wgpu::StorageTextureAccess::WriteOnly
+                    },
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            })
+            .chain(push_constants.layout_entry())
+            .collect();
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &push_constants.ranges(),
+        });
+
+        let mut constants = HashMap::new();
+        constants.insert(String::from("ITERATIONS"), iterations as f64);
+        constants.insert(String::from("STRENGTH"), settings.denoise_strength as f64);
+
+        let pipeline = context.validate("denoise", |device| {
+            let shader_module = device.create_shader_module(push_constants.shader(
+                wgpu::include_wgsl!("../../../shaders-generated/denoise.wgsl"),
+            ));
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: "main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    zero_initialize_workgroup_memory: true,
+                    vertex_pulling_transform: false,
+                },
+                cache: None,
+            })
+        })?;
+
+        let textures = [(); 2].map(|_| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::STORAGE_BINDING,
+                view_formats: &[],
+            })
+        });
+
+        Ok(Self {
+            context,
+            iterations,
+            bind_group_layout,
+            push_constants,
+            pipeline,
+            textures,
+            samples: Cell::new(1),
+        })
+    }
+
+    pub fn set_samples(&self, samples: u32) {
+        self.samples.set(samples);
+    }
+}
+
+impl PostStage for Denoiser<'_> {
+    fn apply(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error> {
+        let device = self.context.device();
+        let queue = self.context.queue();
+
+        // The post-processor skips the stage without AOV targets
+        let [albedo, normal, position, _] = aov_targets.unwrap();
+        let aov_views = [albedo, normal, position]
+            .map(|target| target.create_view(&wgpu::TextureViewDescriptor::default()));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        for iteration in 0..self.iterations {
+            let src = match iteration {
+                0 => src_texture,
+                _ => &self.textures[(iteration as usize - 1) % 2],
+            };
+            let dst = if iteration == self.iterations - 1 {
+                dst_texture
+            } else {
+                &self.textures[iteration as usize % 2]
+            };
+            let src_view = src.create_view(&wgpu::TextureViewDescriptor::default());
+            let dst_view = dst.create_view(&wgpu::TextureViewDescriptor::default());
+
+            let samples = self.samples.get();
+            let constants: Vec<u8> = [tile.width, tile.height, iteration, samples]
+                .iter()
+                .flat_map(|constant| constant.to_ne_bytes())
+                .collect();
+            let constants_buffer = self.push_constants.create_buffer(device, &constants);
+
+            let views = [
+                &src_view,
+                &aov_views[0],
+                &aov_views[1],
+                &aov_views[2],
+                &dst_view,
+            ];
+            let entries: Vec<_> = views
+                .iter()
+                .enumerate()
+                .map(|(i, view)| wgpu::BindGroupEntry {
+                    binding: i as u32,
+                    resource: wgpu::BindingResource::TextureView(view),
+                })
+                .chain(PushConstants::bind_group_entry(&constants_buffer))
+                .collect();
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &self.bind_group_layout,
+                entries: &entries,
+            });
+
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            self.push_constants.set(&mut compute_pass, &constants);
+            compute_pass.dispatch_workgroups(tile.width.div_ceil(16), tile.height.div_ceil(16), 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+        Ok(())
+    }
+
+    fn needs_aovs(&self) -> bool {
+        true
+    }
+}
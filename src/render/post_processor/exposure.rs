@@ -0,0 +1,336 @@
+use std::{cell::Cell, collections::HashMap, sync::mpsc};
+
+use crate::settings::RenderSettings;
+use crate::{
+    error::Error,
+    render::{RenderContext, Tile},
+};
+
+use super::{PixelStage, PostStage};
+
+// These have to match the constants in the histogram shader
+const NUM_BINS: u32 = 256;
+const MIN_LOG_LUMINANCE: f32 = -16.0;
+const MAX_LOG_LUMINANCE: f32 = 16.0;
+
+// The luminance the chosen percentile is exposed to
+const MIDDLE_GRAY: f32 = 0.18;
+
+pub struct Exposure<'a> {
+    context: &'a RenderContext,
+    stage: PixelStage<'a>,
+    exposure: f32,
+    auto_exposure: Option<AutoExposure<'a>>,
+    metered: Cell<Option<f32>>,
+    // The exposure is a uniform rather than a constant since it may be metered after rendering
+    exposure_buffer: wgpu::Buffer,
+}
+
+impl<'a> Exposure<'a> {
+    pub fn new(context: &'a RenderContext, settings: &RenderSettings) -> Result<Self, Error> {
+        let stage = PixelStage::new(
+            context,
+            wgpu::include_wgsl!("../../../shaders-generated/exposure.wgsl"),
+            &HashMap::new(),
+            wgpu::TextureFormat::Rgba32Float,
+            &[wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            }],
+        )?;
+
+        let exposure_buffer = context.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        context
+            .queue()
+            .write_buffer(&exposure_buffer, 0, &settings.exposure.to_ne_bytes());
+
+        let auto_exposure = match settings.auto_exposure {
+            true => Some(AutoExposure::new(context, settings)?),
+            false => None,
+        };
+
+        Ok(Self {
+            context,
+            stage,
+            exposure: settings.exposure,
+            auto_exposure,
+            metered: Cell::new(None),
+            exposure_buffer,
+        })
+    }
+
+    // The metered exposure in EV, which includes the compensation by `--exposure`
+    pub fn metered(&self) -> Option<f32> {
+        self.metered.get()
+    }
+}
+
+impl PostStage for Exposure<'_> {
+    // The exposure is metered from the first tile, since auto exposure isn't supported for tiled
+    // renders
+    fn apply(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        _aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error> {
+        if let Some(auto_exposure) = &self.auto_exposure {
+            if self.metered.get().is_none() {
+                let exposure = auto_exposure.compute(src_texture)? + self.exposure;
+                self.context.queue().write_buffer(
+                    &self.exposure_buffer,
+                    0,
+                    &exposure.to_ne_bytes(),
+                );
+                self.metered.set(Some(exposure));
+            }
+        }
+
+        self.stage.dispatch(
+            src_texture,
+            dst_texture,
+            tile,
+            &[self.exposure_buffer.as_entire_binding()],
+        );
+        Ok(())
+    }
+}
+
+pub struct AutoExposure<'a> {
+    context: &'a RenderContext,
+    percentile: f32,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline: wgpu::ComputePipeline,
+    histogram_buffer: wgpu::Buffer,
+}
+
+impl<'a> AutoExposure<'a> {
+    pub fn new(context: &'a RenderContext, settings: &RenderSettings) -> Result<Self, Error> {
+        let device = context.device();
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::ReadOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = context.validate("histogram", |device| {
+            let shader_module = device.create_shader_module(wgpu::include_wgsl!(
+                "../../../shaders-generated/histogram.wgsl"
+            ));
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: "main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &HashMap::new(),
+                    zero_initialize_workgroup_memory: true,
+                    vertex_pulling_transform: false,
+                },
+                cache: None,
+            })
+        })?;
+
+        let histogram_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (NUM_BINS * 4) as u64,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Ok(Self {
+            context,
+            percentile: settings.exposure_percentile.clamp(0.0, 100.0) / 100.0,
+            bind_group_layout,
+            pipeline,
+            histogram_buffer,
+        })
+    }
+
+    // Returns the exposure in EV that maps the luminance at the percentile to middle gray
+    pub fn compute(&self, src_texture: &wgpu::Texture) -> Result<f32, Error> {
+        Ok(meter(&self.histogram(src_texture)?, self.percentile))
+    }
+
+    fn histogram(&self, src_texture: &wgpu::Texture) -> Result<Vec<u32>, Error> {
+        let device = self.context.device();
+        let queue = self.context.queue();
+
+        let src_view = src_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&src_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: self.histogram_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (NUM_BINS * 4) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.clear_buffer(&self.histogram_buffer, 0, None);
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                src_texture.width().div_ceil(16),
+                src_texture.height().div_ceil(16),
+                1,
+            );
+        }
+
+        encoder.copy_buffer_to_buffer(
+            &self.histogram_buffer,
+            0,
+            &staging_buffer,
+            0,
+            (NUM_BINS * 4) as u64,
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let (tx, rx) = mpsc::channel();
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        rx.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let histogram = slice
+            .get_mapped_range()
+            .chunks_exact(4)
+            .map(|chunk| u32::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+        staging_buffer.unmap();
+
+        Ok(histogram)
+    }
+}
+
+// The exposure in EV from the histogram, whose first bin of pixels darker than its range is left out
+fn meter(histogram: &[u32], percentile: f32) -> f32 {
+    let total: u32 = histogram[1..].iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+
+    let target = (percentile * total as f32).ceil().max(1.0) as u32;
+    let mut count = 0;
+    let bin = histogram[1..]
+        .iter()
+        .position(|pixels| {
+            count += pixels;
+            count >= target
+        })
+        .unwrap();
+
+    let log_luminance = MIN_LOG_LUMINANCE
+        + (bin as f32 + 0.5) / (NUM_BINS - 1) as f32 * (MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE);
+    MIDDLE_GRAY.log2() - log_luminance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Half of a bin in EV, which the metering is accurate to
+    const TOLERANCE: f32 = (MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE) / (NUM_BINS - 1) as f32 / 2.0;
+
+    // Bins the pixels like the histogram shader
+    fn histogram(pixels: &[(f32, u32)]) -> Vec<u32> {
+        let mut histogram = vec![0; NUM_BINS as usize];
+        for &(luminance, count) in pixels {
+            let bin = if luminance < MIN_LOG_LUMINANCE.exp2() {
+                0
+            } else {
+                let t = (luminance.log2().clamp(MIN_LOG_LUMINANCE, MAX_LOG_LUMINANCE)
+                    - MIN_LOG_LUMINANCE)
+                    / (MAX_LOG_LUMINANCE - MIN_LOG_LUMINANCE);
+                1 + ((t * (NUM_BINS - 1) as f32) as u32).min(NUM_BINS - 2)
+            };
+            histogram[bin as usize] += count;
+        }
+        histogram
+    }
+
+    fn assert_ev(metered: f32, expected: f32) {
+        assert!(
+            (metered - expected).abs() <= TOLERANCE,
+            "metered {metered} EV instead of {expected}"
+        );
+    }
+
+    #[test]
+    fn middle_gray_is_kept() {
+        assert_ev(meter(&histogram(&[(MIDDLE_GRAY, 100)]), 0.5), 0.0);
+    }
+
+    #[test]
+    fn percentile_selects_bin() {
+        let histogram = histogram(&[(1.0, 50), (4.0, 50)]);
+        assert_ev(meter(&histogram, 0.5), MIDDLE_GRAY.log2());
+        assert_ev(meter(&histogram, 0.9), MIDDLE_GRAY.log2() - 2.0);
+        // The lowest percentile still meters the darkest pixel
+        assert_ev(meter(&histogram, 0.0), MIDDLE_GRAY.log2());
+    }
+
+    #[test]
+    fn black_pixels_are_left_out() {
+        assert_ev(
+            meter(&histogram(&[(0.0, 1000), (MIDDLE_GRAY * 2.0, 10)]), 0.5),
+            -1.0,
+        );
+        assert_eq!(meter(&histogram(&[(0.0, 1000)]), 0.5), 0.0);
+    }
+}
@@ -0,0 +1,301 @@
+use std::{collections::HashMap, fs, slice};
+
+use crate::{
+    error::Error,
+    render::{RenderContext, Tile},
+};
+
+use super::{PixelStage, PostStage};
+
+// The largest LUT loaded, whose 16.7 million entries take 256 MiB as a texture
+const MAX_SIZE: u32 = 256;
+
+// A 3D lookup table in the .cube format of Adobe and Resolve
+pub struct Lut {
+    pub path: String,
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    // The red component varies fastest, then green, then blue
+    pub data: Vec<[f32; 4]>,
+}
+
+impl Lut {
+    pub fn load(path: &str) -> Result<Self, Error> {
+        let source = fs::read_to_string(path).map_err(Error::io(path))?;
+        let error = |reason: String| Error::Lut {
+            path: path.to_string(),
+            reason,
+        };
+
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let mut tokens = line.split_whitespace();
+            let Some(keyword) = tokens.next() else {
+                continue;
+            };
+            let invalid = || error(format!("has an invalid line {}", i + 1));
+
+            match keyword {
+                "LUT_3D_SIZE" => {
+                    let parsed: u32 = tokens
+                        .next()
+                        .and_then(|size| size.parse().ok())
+                        .ok_or_else(invalid)?;
+                    // Checked before the entries are collected
+                    if parsed > MAX_SIZE {
+                        return Err(error(format!("has a size larger than {MAX_SIZE}")));
+                    }
+                    size = Some(parsed);
+                }
+                "LUT_1D_SIZE" => {
+                    return Err(error(String::from("is 1D, which is not supported")));
+                }
+                "DOMAIN_MIN" => domain_min = parse_values(tokens).ok_or_else(invalid)?,
+                "DOMAIN_MAX" => domain_max = parse_values(tokens).ok_or_else(invalid)?,
+                // Resolve writes the same range for all channels
+                "LUT_3D_INPUT_RANGE" => {
+                    let [min, max] = parse_values(tokens).ok_or_else(invalid)?;
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                }
+                // Comments and other keywords like `TITLE` don't affect the colors
+                _ if keyword.starts_with(|c: char| c == '#' || c.is_ascii_alphabetic()) => {}
+                _ => {
+                    let [r, g, b] = parse_values(line.split_whitespace()).ok_or_else(invalid)?;
+                    data.push([r, g, b, 1.0]);
+                }
+            }
+        }
+
+        let size = size.ok_or_else(|| error(String::from("has no LUT_3D_SIZE")))?;
+        if size < 2 {
+            return Err(error(String::from("has a size less than 2")));
+        }
+        let entries = size
+            .checked_pow(3)
+            .ok_or_else(|| error(format!("has a size larger than {MAX_SIZE}")))?;
+        if data.len() != entries as usize {
+            return Err(error(format!(
+                "has {} entries instead of the {entries} of a LUT of size {size}",
+                data.len(),
+            )));
+        }
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            return Err(error(String::from("has an empty domain")));
+        }
+
+        Ok(Self {
+            path: path.to_string(),
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+
+    pub fn create_texture(&self, context: &RenderContext) -> Result<wgpu::Texture, Error> {
+        let max_size = context.device().limits().max_texture_dimension_3d;
+        if self.size > max_size {
+            return Err(Error::Lut {
+                path: self.path.clone(),
+                reason: format!("is larger than {max_size}, the maximum size of the device"),
+            });
+        }
+
+        let size = wgpu::Extent3d {
+            width: self.size,
+            height: self.size,
+            depth_or_array_layers: self.size,
+        };
+        let texture = context.device().create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let data = &self.data;
+        context.queue().write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 16) },
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.size * 16),
+                rows_per_image: Some(self.size),
+            },
+            size,
+        );
+        Ok(texture)
+    }
+}
+
+// The stage applying a LUT to the encoded colors
+pub struct Grading<'a> {
+    stage: PixelStage<'a>,
+    lut_texture: wgpu::Texture,
+    domain_buffer: wgpu::Buffer,
+}
+
+impl<'a> Grading<'a> {
+    pub fn new(context: &'a RenderContext, lut: &Lut) -> Result<Self, Error> {
+        let stage = PixelStage::new(
+            context,
+            wgpu::include_wgsl!("../../../shaders-generated/lut.wgsl"),
+            &HashMap::new(),
+            wgpu::TextureFormat::Rgba32Float,
+            &[
+                wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+            ],
+        )?;
+
+        let domain_buffer = context.device().create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 32,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+        let [min_r, min_g, min_b] = lut.domain_min;
+        let [max_r, max_g, max_b] = lut.domain_max;
+        let domain: Vec<u8> = [min_r, min_g, min_b, 0.0, max_r, max_g, max_b, 0.0]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect();
+        context.queue().write_buffer(&domain_buffer, 0, &domain);
+
+        Ok(Self {
+            stage,
+            lut_texture: lut.create_texture(context)?,
+            domain_buffer,
+        })
+    }
+}
+
+impl PostStage for Grading<'_> {
+    fn apply(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        _aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error> {
+        let lut_view = self
+            .lut_texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.stage.dispatch(
+            src_texture,
+            dst_texture,
+            tile,
+            &[
+                wgpu::BindingResource::TextureView(&lut_view),
+                self.domain_buffer.as_entire_binding(),
+            ],
+        );
+        Ok(())
+    }
+}
+
+fn parse_values<'a, const N: usize>(tokens: impl Iterator<Item = &'a str>) -> Option<[f32; N]> {
+    let values: Vec<f32> = tokens
+        .map(|token| token.parse().ok())
+        .collect::<Option<_>>()?;
+    values.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    // Loads the source from a temporary .cube file
+    fn load(name: &str, source: &str) -> Result<Lut, Error> {
+        let path = env::temp_dir().join(format!("prisma-{}-{name}.cube", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, source).unwrap();
+        let lut = Lut::load(&path);
+        fs::remove_file(&path).unwrap();
+        lut
+    }
+
+    fn reason(result: Result<Lut, Error>) -> String {
+        match result {
+            Err(Error::Lut { reason, .. }) => reason,
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(_) => panic!("the LUT was accepted"),
+        }
+    }
+
+    // The entries of the identity LUT of size 2, red varying fastest
+    const IDENTITY: &str = "0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+
+    #[test]
+    fn identity() {
+        let source = format!("TITLE \"identity\"\n# a comment\nLUT_3D_SIZE 2\n\n{IDENTITY}");
+        let lut = load("identity", &source).unwrap();
+        assert_eq!(lut.size, 2);
+        assert_eq!((lut.domain_min, lut.domain_max), ([0.0; 3], [1.0; 3]));
+        assert_eq!(lut.data[1], [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(lut.data[6], [0.0, 1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn domain() {
+        let source = format!("LUT_3D_SIZE 2\nLUT_3D_INPUT_RANGE -1 2\n{IDENTITY}");
+        let lut = load("range", &source).unwrap();
+        assert_eq!((lut.domain_min, lut.domain_max), ([-1.0; 3], [2.0; 3]));
+
+        let source = format!("LUT_3D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 1 0 1\n{IDENTITY}");
+        assert_eq!(reason(load("empty-domain", &source)), "has an empty domain");
+    }
+
+    #[test]
+    fn bad_size() {
+        let sized = |name, size| reason(load(name, &format!("LUT_3D_SIZE {size}\n{IDENTITY}")));
+        assert_eq!(sized("small", "1"), "has a size less than 2");
+        assert_eq!(sized("large", "257"), "has a size larger than 256");
+        assert_eq!(
+            sized("overflow", "4294967295"),
+            "has a size larger than 256"
+        );
+        assert_eq!(sized("invalid", "two"), "has an invalid line 1");
+        assert_eq!(reason(load("no-size", IDENTITY)), "has no LUT_3D_SIZE");
+    }
+
+    #[test]
+    fn wrong_entry_count() {
+        let source = format!("LUT_3D_SIZE 3\n{IDENTITY}");
+        assert_eq!(
+            reason(load("count", &source)),
+            "has 8 entries instead of the 27 of a LUT of size 3"
+        );
+        let source = format!("LUT_3D_SIZE 2\n{IDENTITY}1 1\n");
+        assert_eq!(reason(load("entry", &source)), "has an invalid line 10");
+    }
+
+    #[test]
+    fn one_dimensional() {
+        let source = "LUT_1D_SIZE 2\n0 0 0\n1 1 1\n";
+        assert_eq!(reason(load("1d", source)), "is 1D, which is not supported");
+    }
+}
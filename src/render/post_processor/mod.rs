@@ -0,0 +1,452 @@
+mod bloom;
+mod denoiser;
+mod exposure;
+mod lut;
+mod pixel_stage;
+
+use std::{collections::HashMap, rc::Rc, sync::mpsc};
+
+use image::{DynamicImage, ImageBuffer, RgbaImage};
+
+use crate::{
+    config::{value_name, BitDepth, Size, Stage},
+    error::Error,
+    settings::RenderSettings,
+};
+
+use super::{RenderContext, Tile};
+
+use bloom::Bloom;
+use denoiser::Denoiser;
+use exposure::Exposure;
+use lut::{Grading, Lut};
+use pixel_stage::PixelStage;
+
+// A step of post-processing, which reads the radiance or the colors of a tile from the source and
+// writes the result to the destination
+pub trait PostStage {
+    fn apply(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error>;
+
+    // Stages guided by the AOVs are skipped without them
+    fn needs_aovs(&self) -> bool {
+        false
+    }
+}
+
+// Lets the post-processor keep a handle to a stage in the chain
+impl<T: PostStage + ?Sized> PostStage for Rc<T> {
+    fn apply(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error> {
+        (**self).apply(src_texture, dst_texture, tile, aov_targets)
+    }
+
+    fn needs_aovs(&self) -> bool {
+        (**self).needs_aovs()
+    }
+}
+
+pub struct PostProcessor<'a> {
+    context: &'a RenderContext,
+    bit_depth: BitDepth,
+    resolve: PixelStage<'a>,
+    samples_buffer: wgpu::Buffer,
+    stages: Vec<Box<dyn PostStage + 'a>>,
+    exposure: Option<Rc<Exposure<'a>>>,
+    denoiser: Option<Rc<Denoiser<'a>>>,
+    quantize: PixelStage<'a>,
+    // The stages alternate between the textures
+    textures: [wgpu::Texture; 2],
+    dst_texture: wgpu::Texture,
+}
+
+impl<'a> PostProcessor<'a> {
+    pub fn new(context: &'a RenderContext, settings: &RenderSettings) -> Result<Self, Error> {
+        let device = context.device();
+
+        // The post-processor works on a single tile at a time
+        let Size { width, height } = Tile::max_size(context, settings);
+
+        check_order(&settings.post_stages)?;
+
+        let bit_depth = settings.bit_depth;
+
+        let resolve = PixelStage::new(
+            context,
+            wgpu::include_wgsl!("../../../shaders-generated/resolve.wgsl"),
+            &HashMap::new(),
+            wgpu::TextureFormat::Rgba32Float,
+            &[wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            }],
+        )?;
+        let samples_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 4,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        });
+
+        let mut stages: Vec<Box<dyn PostStage + 'a>> = Vec::new();
+        let mut exposure = None;
+        let mut denoiser = None;
+        for stage in &settings.post_stages {
+            match stage {
+                Stage::Exposure => {
+                    let stage = Rc::new(Exposure::new(context, settings)?);
+                    exposure = Some(Rc::clone(&stage));
+                    stages.push(Box::new(stage));
+                }
+                Stage::Denoise if settings.denoise => {
+                    let stage = Rc::new(Denoiser::new(context, settings)?);
+                    denoiser = Some(Rc::clone(&stage));
+                    stages.push(Box::new(stage));
+                }
+                Stage::Bloom if settings.bloom => {
+                    stages.push(Box::new(Bloom::new(context, settings)?));
+                }
+                Stage::ToneMap => {
+                    let mut constants = HashMap::new();
+                    constants.insert(
+                        String::from("TONE_MAPPING"),
+                        settings.tone_mapping as u32 as f64,
+                    );
+                    constants.insert(
+                        String::from("COLOR_SPACE"),
+                        settings.color_space as u32 as f64,
+                    );
+                    if let Some(white_point) = settings.white_point {
+                        constants.insert(String::from("WHITE_POINT"), white_point as f64);
+                    }
+                    stages.push(Box::new(PixelStage::new(
+                        context,
+                        wgpu::include_wgsl!("../../../shaders-generated/tone_map.wgsl"),
+                        &constants,
+                        wgpu::TextureFormat::Rgba32Float,
+                        &[],
+                    )?));
+                }
+                Stage::Lut => {
+                    if let Some(path) = &settings.lut {
+                        stages.push(Box::new(Grading::new(context, &Lut::load(path)?)?));
+                    }
+                }
+                Stage::Vignette if settings.vignette > 0.0 => {
+                    let mut constants = HashMap::new();
+                    constants.insert(String::from("WIDTH"), settings.size.width as f64);
+                    constants.insert(String::from("HEIGHT"), settings.size.height as f64);
+                    constants.insert(String::from("STRENGTH"), settings.vignette as f64);
+                    stages.push(Box::new(PixelStage::new(
+                        context,
+                        wgpu::include_wgsl!("../../../shaders-generated/vignette.wgsl"),
+                        &constants,
+                        wgpu::TextureFormat::Rgba32Float,
+                        &[],
+                    )?));
+                }
+                Stage::Dither if settings.dither => {
+                    let mut constants = HashMap::new();
+                    let levels = match bit_depth {
+                        BitDepth::Eight => u8::MAX as f64,
+                        BitDepth::Sixteen => u16::MAX as f64,
+                    };
+                    constants.insert(String::from("LEVELS"), levels);
+                    stages.push(Box::new(PixelStage::new(
+                        context,
+                        wgpu::include_wgsl!("../../../shaders-generated/dither.wgsl"),
+                        &constants,
+                        wgpu::TextureFormat::Rgba32Float,
+                        &[],
+                    )?));
+                }
+                _ => {}
+            }
+        }
+
+        let (quantize_shader, dst_format) = match bit_depth {
+            BitDepth::Eight => (
+                wgpu::include_wgsl!("../../../shaders-generated/quantize.wgsl"),
+                wgpu::TextureFormat::Rgba8Unorm,
+            ),
+            BitDepth::Sixteen => (
+                wgpu::include_wgsl!("../../../shaders-generated/quantize_float.wgsl"),
+                wgpu::TextureFormat::Rgba32Float,
+            ),
+        };
+        let quantize = PixelStage::new(context, quantize_shader, &HashMap::new(), dst_format, &[])?;
+
+        let create_texture = |format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let textures = [(); 2].map(|_| {
+            create_texture(
+                wgpu::TextureFormat::Rgba32Float,
+                wgpu::TextureUsages::STORAGE_BINDING,
+            )
+        });
+        let dst_texture = create_texture(
+            dst_format,
+            wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        );
+
+        Ok(Self {
+            context,
+            bit_depth,
+            resolve,
+            samples_buffer,
+            stages,
+            exposure,
+            denoiser,
+            quantize,
+            textures,
+            dst_texture,
+        })
+    }
+
+    // The exposure metered by `--auto-exposure` once the first tile is post-processed
+    pub fn metered_exposure(&self) -> Option<f32> {
+        self.exposure.as_ref()?.metered()
+    }
+
+    // The accumulation of the given number of samples is run through the stages, of which those
+    // guided by the AOVs are skipped without AOV targets
+    pub fn post_process(
+        &self,
+        src_texture: &wgpu::Texture,
+        samples: u32,
+        tile: &Tile,
+        aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error> {
+        let [mut src, mut dst] = [&self.textures[0], &self.textures[1]];
+        self.context
+            .queue()
+            .write_buffer(&self.samples_buffer, 0, &samples.to_ne_bytes());
+        if let Some(denoiser) = &self.denoiser {
+            denoiser.set_samples(samples);
+        }
+        self.resolve.dispatch(
+            src_texture,
+            src,
+            tile,
+            &[self.samples_buffer.as_entire_binding()],
+        );
+        for stage in &self.stages {
+            if stage.needs_aovs() && aov_targets.is_none() {
+                continue;
+            }
+            stage.apply(src, dst, tile, aov_targets)?;
+            (src, dst) = (dst, src);
+        }
+        self.quantize.apply(src, &self.dst_texture, tile, None)
+    }
+
+    // Reads back the post-processed tile with 8 or 16 bits per channel
+    pub async fn retrieve_result(&self, tile: &Tile) -> Result<DynamicImage, Error> {
+        let device = self.context.device();
+        let queue = self.context.queue();
+
+        let block_size = self.dst_texture.format().block_copy_size(None).unwrap();
+        let padded_row_size = (tile.width * block_size).next_multiple_of(256);
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_row_size * tile.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.dst_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: tile.width,
+                height: tile.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        queue.submit(Some(encoder.finish()));
+
+        let (tx, rx) = mpsc::channel();
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        rx.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let mut buffer = Vec::with_capacity((tile.width * tile.height * block_size) as usize);
+        {
+            let view = slice.get_mapped_range();
+            for row in view.chunks_exact(padded_row_size as usize) {
+                buffer.extend_from_slice(&row[..(tile.width * block_size) as usize]);
+            }
+        }
+        staging_buffer.unmap();
+
+        let image = match self.bit_depth {
+            BitDepth::Eight => DynamicImage::ImageRgba8(
+                RgbaImage::from_raw(tile.width, tile.height, buffer).unwrap(),
+            ),
+            BitDepth::Sixteen => {
+                let data = buffer
+                    .chunks_exact(4)
+                    .map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap()))
+                    .map(|value| (value * u16::MAX as f32).round() as u16)
+                    .collect();
+                DynamicImage::ImageRgba16(
+                    ImageBuffer::from_raw(tile.width, tile.height, data).unwrap(),
+                )
+            }
+        };
+        Ok(image)
+    }
+}
+
+// Tone mapping separates the stages working with the radiance from those working with the encoded
+// colors of the display
+fn check_order(stages: &[Stage]) -> Result<(), Error> {
+    let tone_map = stages
+        .iter()
+        .position(|stage| *stage == Stage::ToneMap)
+        .ok_or_else(|| {
+            Error::Settings(String::from(
+                "the post-processing stages must include tonemap",
+            ))
+        })?;
+    for (i, stage) in stages.iter().enumerate() {
+        if stages[..i].contains(stage) {
+            return Err(Error::Settings(format!(
+                "the post-processing stage {} is given twice",
+                value_name(*stage)
+            )));
+        }
+        match stage {
+            Stage::Exposure | Stage::Denoise | Stage::Bloom if i > tone_map => {
+                return Err(Error::Settings(format!(
+                    "the {} stage has to come before tonemap",
+                    value_name(*stage)
+                )));
+            }
+            Stage::Lut | Stage::Dither if i < tone_map => {
+                return Err(Error::Settings(format!(
+                    "the {} stage has to come after tonemap",
+                    value_name(*stage)
+                )));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(stages: &[Stage]) -> String {
+        match check_order(stages) {
+            Err(Error::Settings(message)) => message,
+            Err(error) => panic!("unexpected error: {error}"),
+            Ok(()) => panic!("the stages were accepted"),
+        }
+    }
+
+    #[test]
+    fn default_order() {
+        let stages = [
+            Stage::Exposure,
+            Stage::Denoise,
+            Stage::Bloom,
+            Stage::ToneMap,
+            Stage::Lut,
+            Stage::Dither,
+        ];
+        assert!(check_order(&stages).is_ok());
+        assert!(check_order(&[Stage::ToneMap]).is_ok());
+    }
+
+    #[test]
+    fn missing_tonemap() {
+        assert_eq!(
+            message(&[Stage::Exposure, Stage::Lut]),
+            "the post-processing stages must include tonemap"
+        );
+        assert_eq!(
+            message(&[]),
+            "the post-processing stages must include tonemap"
+        );
+    }
+
+    #[test]
+    fn duplicate_stage() {
+        assert_eq!(
+            message(&[Stage::Bloom, Stage::ToneMap, Stage::Bloom]),
+            "the post-processing stage bloom is given twice"
+        );
+        assert_eq!(
+            message(&[Stage::ToneMap, Stage::ToneMap]),
+            "the post-processing stage tonemap is given twice"
+        );
+    }
+
+    #[test]
+    fn stage_before_tonemap() {
+        assert_eq!(
+            message(&[Stage::ToneMap, Stage::Exposure]),
+            "the exposure stage has to come before tonemap"
+        );
+        assert_eq!(
+            message(&[Stage::ToneMap, Stage::Denoise]),
+            "the denoise stage has to come before tonemap"
+        );
+    }
+
+    #[test]
+    fn stage_after_tonemap() {
+        assert_eq!(
+            message(&[Stage::Lut, Stage::ToneMap]),
+            "the lut stage has to come after tonemap"
+        );
+        assert_eq!(
+            message(&[Stage::Dither, Stage::ToneMap]),
+            "the dither stage has to come after tonemap"
+        );
+    }
+}
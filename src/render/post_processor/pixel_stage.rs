@@ -0,0 +1,172 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    error::Error,
+    render::{PushConstants, RenderContext, Tile},
+};
+
+use super::PostStage;
+
+// A stage processing every pixel on its own with a shader including `post_stage.wgsl`, whose
+// bindings after the source and the destination are given as extra bindings
+pub struct PixelStage<'a> {
+    context: &'a RenderContext,
+    bind_group_layout: wgpu::BindGroupLayout,
+    push_constants: PushConstants,
+    pipeline: wgpu::ComputePipeline,
+}
+
+impl<'a> PixelStage<'a> {
+    pub fn new(
+        context: &'a RenderContext,
+        shader: wgpu::ShaderModuleDescriptor,
+        constants: &HashMap<String, f64>,
+        dst_format: wgpu::TextureFormat,
+        extra_bindings: &[wgpu::BindingType],
+    ) -> Result<Self, Error> {
+        let device = context.device();
+        let push_constants = PushConstants::new(context, 16);
+
+        let mut entries = vec![
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::ReadOnly,
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: dst_format,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+        ];
+        entries.extend(extra_bindings.iter().enumerate().map(|(i, ty)| {
+            wgpu::BindGroupLayoutEntry {
+                binding: 2 + i as u32,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: *ty,
+                count: None,
+            }
+        }));
+        entries.extend(push_constants.layout_entry());
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &entries,
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &push_constants.ranges(),
+        });
+
+        // `include_wgsl!` labels the shader with its path
+        let name = shader
+            .label
+            .and_then(|label| Path::new(label).file_stem())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let pipeline = context.validate(&name, |device| {
+            let shader_module = device.create_shader_module(push_constants.shader(shader));
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: "main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants,
+                    zero_initialize_workgroup_memory: true,
+                    vertex_pulling_transform: false,
+                },
+                cache: None,
+            })
+        })?;
+
+        Ok(Self {
+            context,
+            bind_group_layout,
+            push_constants,
+            pipeline,
+        })
+    }
+
+    pub fn dispatch(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        extra_resources: &[wgpu::BindingResource],
+    ) {
+        let device = self.context.device();
+        let queue = self.context.queue();
+
+        let constants: Vec<u8> = [tile.x, tile.y, tile.width, tile.height]
+            .iter()
+            .flat_map(|constant| constant.to_ne_bytes())
+            .collect();
+        let constants_buffer = self.push_constants.create_buffer(device, &constants);
+
+        let src_view = src_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let dst_view = dst_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&dst_view),
+            },
+        ];
+        entries.extend(extra_resources.iter().enumerate().map(|(i, resource)| {
+            wgpu::BindGroupEntry {
+                binding: 2 + i as u32,
+                resource: resource.clone(),
+            }
+        }));
+        entries.extend(PushConstants::bind_group_entry(&constants_buffer));
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_group_layout,
+            entries: &entries,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &bind_group, &[]);
+            self.push_constants.set(&mut compute_pass, &constants);
+            compute_pass.dispatch_workgroups(tile.width.div_ceil(16), tile.height.div_ceil(16), 1);
+        }
+
+        queue.submit(Some(encoder.finish()));
+    }
+}
+
+impl PostStage for PixelStage<'_> {
+    fn apply(
+        &self,
+        src_texture: &wgpu::Texture,
+        dst_texture: &wgpu::Texture,
+        tile: &Tile,
+        _aov_targets: Option<&[wgpu::Texture; 4]>,
+    ) -> Result<(), Error> {
+        self.dispatch(src_texture, dst_texture, tile, &[]);
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+
+use wgpu::util::DeviceExt;
+
+use super::RenderContext;
+
+// The binding of the uniform buffer standing in for the push constants, which is in the first bind
+// group after the bindings of the shaders
+const BINDING: u32 = 15;
+
+// The constants of a compute pipeline, which are pushed if the adapter supports it and are bound
+// as a uniform buffer otherwise
+pub struct PushConstants {
+    size: u32,
+    emulated: bool,
+}
+
+impl PushConstants {
+    pub fn new(context: &RenderContext, size: u32) -> Self {
+        Self {
+            size,
+            emulated: !context.has_push_constants(),
+        }
+    }
+
+    pub fn ranges(&self) -> Vec<wgpu::PushConstantRange> {
+        match self.emulated {
+            true => Vec::new(),
+            false => vec![wgpu::PushConstantRange {
+                stages: wgpu::ShaderStages::COMPUTE,
+                range: 0..self.size,
+            }],
+        }
+    }
+
+    // The entry to append to the layout of the first bind group
+    pub fn layout_entry(&self) -> Option<wgpu::BindGroupLayoutEntry> {
+        self.emulated.then_some(wgpu::BindGroupLayoutEntry {
+            binding: BINDING,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        })
+    }
+
+    // The shaders declare their constants as `var<push_constant>`, which is rewritten into the
+    // uniform binding
+    pub fn shader<'a>(
+        &self,
+        shader: wgpu::ShaderModuleDescriptor<'a>,
+    ) -> wgpu::ShaderModuleDescriptor<'a> {
+        match (self.emulated, shader.source) {
+            (true, wgpu::ShaderSource::Wgsl(source)) => wgpu::ShaderModuleDescriptor {
+                label: shader.label,
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(source.replace(
+                    "var<push_constant>",
+                    &format!("@group(0) @binding({BINDING}) var<uniform>"),
+                ))),
+            },
+            (_, source) => wgpu::ShaderModuleDescriptor {
+                label: shader.label,
+                source,
+            },
+        }
+    }
+
+    // A buffer holding the constants of a single dispatch, since all writes to a buffer land
+    // before the submission
+    pub fn create_buffer(&self, device: &wgpu::Device, constants: &[u8]) -> Option<wgpu::Buffer> {
+        self.emulated.then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: constants,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        })
+    }
+
+    pub fn bind_group_entry(buffer: &Option<wgpu::Buffer>) -> Option<wgpu::BindGroupEntry<'_>> {
+        buffer.as_ref().map(|buffer| wgpu::BindGroupEntry {
+            binding: BINDING,
+            resource: buffer.as_entire_binding(),
+        })
+    }
+
+    // Emulated constants are written into their buffer instead, which is bound with the first bind
+    // group
+    pub fn set(&self, compute_pass: &mut wgpu::ComputePass, constants: &[u8]) {
+        if !self.emulated {
+            compute_pass.set_push_constants(0, constants);
+        }
+    }
+}
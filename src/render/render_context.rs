@@ -1,28 +1,110 @@
+use std::cell::RefCell;
+
+use itertools::Itertools;
+
+use crate::{
+    config::{value_name, Backend},
+    error::Error,
+    settings::RenderSettings,
+};
+
+// The features the renderer can't do without
+const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+// The features which are emulated on adapters without them
+const OPTIONAL_FEATURES: wgpu::Features =
+    wgpu::Features::PUSH_CONSTANTS.union(BINDING_ARRAY_FEATURES);
+// The textures are packed into an atlas without these
+const BINDING_ARRAY_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_BINDING_ARRAY
+    .union(wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING);
+// The storage textures of the render target and the four AOV targets, which the denoiser binds as
+// many of. Without AOVs the bloom binds the most, which fit into the downlevel limit.
+const AOV_STORAGE_TEXTURES: u32 = 5;
+
 pub struct RenderContext {
     device: wgpu::Device,
     queue: wgpu::Queue,
+    adapter_info: wgpu::AdapterInfo,
+    // The storage buffers of the scene with their sizes and the bindings they are split into, in
+    // the order of their creation
+    storage_buffers: RefCell<Vec<(&'static str, u64, u32)>>,
 }
 
 impl RenderContext {
     // Constructor which may fail should add a `try_` prefix to the function name
-    pub async fn try_new() -> Result<Self, wgpu::RequestDeviceError> {
-        let instance = wgpu::Instance::default();
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
-            .await
-            .unwrap();
+    pub async fn try_new(settings: &RenderSettings) -> Result<Self, Error> {
+        let (instance, backends) = create_instance(settings.backend);
+        let backend = match settings.backend {
+            Some(backend) => format!("the {} backend", value_name(backend)),
+            None => String::from("any backend"),
+        };
+        let adapter = match &settings.adapter {
+            Some(name) => {
+                let adapters = instance.enumerate_adapters(backends);
+                if adapters.is_empty() {
+                    return Err(Error::Adapter(format!("no adapter was found by {backend}")));
+                }
+                let names = adapters
+                    .iter()
+                    .map(|adapter| format!("\"{}\"", adapter.get_info().name))
+                    .join(", ");
+                adapters
+                    .into_iter()
+                    .find(|adapter| {
+                        let adapter_name = adapter.get_info().name.to_lowercase();
+                        adapter_name.contains(&name.to_lowercase())
+                    })
+                    .ok_or_else(|| {
+                        Error::Adapter(format!("none of the adapters {names} is named like {name}"))
+                    })?
+            }
+            None => instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    force_fallback_adapter: settings.fallback_adapter,
+                    ..Default::default()
+                })
+                .await
+                .ok_or_else(|| {
+                    Error::Adapter(match settings.fallback_adapter {
+                        true => format!("no fallback adapter was found by {backend}"),
+                        false => format!("no adapter was found by {backend}"),
+                    })
+                })?,
+        };
+
+        let missing = REQUIRED_FEATURES - adapter.features();
+        if !missing.is_empty() {
+            return Err(Error::Adapter(format!(
+                "{} lacks the features {}",
+                adapter.get_info().name,
+                feature_names(missing)
+            )));
+        }
+        let features = REQUIRED_FEATURES | (OPTIONAL_FEATURES & adapter.features());
+
         let mut limits = wgpu::Limits::downlevel_defaults();
         limits.max_bind_groups = 5;
-        limits.max_push_constant_size = 4;
-        limits.max_texture_dimension_2d = 8192;
+        // Large scenes need all the storage buffers of the adapter, which are checked against the
+        // scene before rendering
+        let adapter_limits = adapter.limits();
+        limits.max_storage_buffers_per_shader_stage =
+            adapter_limits.max_storage_buffers_per_shader_stage;
+        limits.max_storage_buffer_binding_size = adapter_limits.max_storage_buffer_binding_size;
+        limits.max_buffer_size = adapter_limits.max_buffer_size;
+        if features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            limits.max_push_constant_size = 24;
+        }
+        // Without the storage textures the AOVs and the denoiser are turned off when rendering
+        if settings.renders_aovs()
+            && adapter_limits.max_storage_textures_per_shader_stage >= AOV_STORAGE_TEXTURES
+        {
+            limits.max_storage_textures_per_shader_stage = AOV_STORAGE_TEXTURES;
+        }
+        limits.max_texture_dimension_2d = adapter_limits.max_texture_dimension_2d.min(8192);
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                        | wgpu::Features::PUSH_CONSTANTS
-                        | wgpu::Features::TEXTURE_BINDING_ARRAY
-                        | wgpu::Features::SAMPLED_TEXTURE_AND_STORAGE_BUFFER_ARRAY_NON_UNIFORM_INDEXING,
+                    required_features: features,
                     required_limits: limits,
                     memory_hints: wgpu::MemoryHints::Performance,
                 },
@@ -30,7 +112,63 @@ impl RenderContext {
             )
             .await?;
 
-        Ok(Self { device, queue })
+        Ok(Self {
+            device,
+            queue,
+            adapter_info: adapter.get_info(),
+            storage_buffers: RefCell::new(Vec::new()),
+        })
+    }
+
+    // Describes the adapters of the backend with the features they lack
+    pub fn list_adapters(backend: Option<Backend>) -> Vec<String> {
+        let (instance, backends) = create_instance(backend);
+        instance
+            .enumerate_adapters(backends)
+            .iter()
+            .map(|adapter| {
+                let info = adapter.get_info();
+                let mut line = format!(
+                    "{} ({:?}, {})",
+                    info.name,
+                    info.device_type,
+                    info.backend.to_str()
+                );
+                let driver = format!("{} {}", info.driver, info.driver_info);
+                if !driver.trim().is_empty() {
+                    line += &format!(", driver {}", driver.trim());
+                }
+                let missing = REQUIRED_FEATURES - adapter.features();
+                if !missing.is_empty() {
+                    line += &format!(", unsupported without {}", feature_names(missing));
+                }
+                let missing = OPTIONAL_FEATURES - adapter.features();
+                if !missing.is_empty() {
+                    line += &format!(", emulating {}", feature_names(missing));
+                }
+                if adapter.limits().max_storage_textures_per_shader_stage < AOV_STORAGE_TEXTURES {
+                    line += ", without AOVs and denoising";
+                }
+                line
+            })
+            .collect()
+    }
+
+    // Whether the device binds the storage textures of the AOV targets
+    pub fn supports_aovs(&self) -> bool {
+        self.device.limits().max_storage_textures_per_shader_stage >= AOV_STORAGE_TEXTURES
+    }
+
+    // Whether the constants of the shaders are pushed or bound as uniform buffers
+    pub fn has_push_constants(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::PUSH_CONSTANTS)
+    }
+
+    // Whether the textures can be bound as an array, or have to be packed into an atlas
+    pub fn has_binding_arrays(&self) -> bool {
+        self.device.features().contains(BINDING_ARRAY_FEATURES)
     }
 
     pub fn device(&self) -> &wgpu::Device {
@@ -40,4 +178,121 @@ impl RenderContext {
     pub fn queue(&self) -> &wgpu::Queue {
         &self.queue
     }
+
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    pub fn buffer_sizes(&self) -> Vec<(&'static str, u64)> {
+        self.storage_buffers
+            .borrow()
+            .iter()
+            .map(|(buffer, size, _)| (*buffer, *size))
+            .collect()
+    }
+
+    // The largest storage buffer which can be created and bound as a whole
+    pub fn max_binding_size(&self) -> u64 {
+        let limits = self.device.limits();
+        (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+    }
+
+    // Creates the shader modules and pipelines of a shader, whose validation errors would otherwise
+    // be reported by the panicking default handler
+    pub fn validate<T>(
+        &self,
+        shader: &str,
+        create: impl FnOnce(&wgpu::Device) -> T,
+    ) -> Result<T, Error> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let created = create(&self.device);
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(error) => Err(Error::Shader {
+                shader: shader.to_string(),
+                message: error.to_string(),
+            }),
+            None => Ok(created),
+        }
+    }
+
+    // Checks the size of a storage buffer which can't be split against the binding limit of the
+    // device, and records it for the statistics of the render
+    pub fn check_buffer_size(&self, buffer: &'static str, size: u64) -> Result<(), Error> {
+        self.add_storage_buffer(buffer, size, 1);
+        let limit = self.max_binding_size();
+        if size > limit {
+            return Err(Error::Limits(vec![format!(
+                "the {buffer} buffer of {size} bytes exceeds {} of {limit} bytes",
+                self.binding_size_limit()
+            )]));
+        }
+        Ok(())
+    }
+
+    // Records a storage buffer which is bound as the given number of chunks
+    pub fn add_storage_buffer(&self, buffer: &'static str, size: u64, bindings: u32) {
+        self.storage_buffers
+            .borrow_mut()
+            .push((buffer, size, bindings));
+    }
+
+    // Checks the bindings of the storage buffers recorded so far and of those still to be bound
+    // against the limit of the device, which splitting the large buffers may exceed
+    pub fn check_limits(&self, other_bindings: u32) -> Result<(), Error> {
+        let storage_buffers = self.storage_buffers.borrow();
+        let bindings = other_bindings
+            + storage_buffers
+                .iter()
+                .map(|(_, _, bindings)| bindings)
+                .sum::<u32>();
+        let limit = self.device.limits().max_storage_buffers_per_shader_stage;
+        if bindings <= limit {
+            return Ok(());
+        }
+
+        let mut exceeded = vec![format!(
+            "the {bindings} storage buffer bindings exceed max_storage_buffers_per_shader_stage of {limit}"
+        )];
+        let max_binding_size = self.max_binding_size();
+        exceeded.extend(
+            storage_buffers
+                .iter()
+                .filter(|(_, _, bindings)| *bindings > 1)
+                .map(|(buffer, size, bindings)| {
+                    format!(
+                        "the {buffer} buffer of {size} bytes exceeds {} of {max_binding_size} bytes, and is split into {bindings} bindings",
+                        self.binding_size_limit()
+                    )
+                }),
+        );
+        Err(Error::Limits(exceeded))
+    }
+
+    fn binding_size_limit(&self) -> &'static str {
+        let limits = self.device.limits();
+        match limits.max_storage_buffer_binding_size as u64 <= limits.max_buffer_size {
+            true => "max_storage_buffer_binding_size",
+            false => "max_buffer_size",
+        }
+    }
+}
+
+// Without a backend, all backends which are available on the platform are used
+fn create_instance(backend: Option<Backend>) -> (wgpu::Instance, wgpu::Backends) {
+    let backends = match backend {
+        Some(Backend::Vulkan) => wgpu::Backends::VULKAN,
+        Some(Backend::Metal) => wgpu::Backends::METAL,
+        Some(Backend::Dx12) => wgpu::Backends::DX12,
+        Some(Backend::Gl) => wgpu::Backends::GL,
+        None => wgpu::Backends::all(),
+    };
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+    (instance, backends)
+}
+
+fn feature_names(features: wgpu::Features) -> String {
+    features.iter_names().map(|(name, _)| name).join(", ")
 }
@@ -1,19 +1,70 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::mpsc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use indicatif::ProgressBar;
 
-use crate::config::{Config, Size};
+use crate::{
+    config::{GeometryLayout, Size},
+    error::Error,
+    settings::RenderSettings,
+    textures,
+};
 
-use super::RenderContext;
+use super::{
+    Aovs, Checkpoint, PushConstants, RenderContext, StorageArray, StorageArrayLayout, Tile,
+};
+
+// The albedo and the sample count, the normal and the depth, the position and the second moment of
+// the luminance, and the IDs of the first hits
+const AOV_FORMATS: [wgpu::TextureFormat; 4] = [
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::Rgba32Float,
+    wgpu::TextureFormat::Rgba32Uint,
+];
+
+// The variants of the render shader, indexed by whether the textures are packed into an atlas, the
+// geometry is compact and the AOVs are left out
+const RENDER_SHADERS: [&str; 8] = [
+    include_str!("../../shaders-generated/render.wgsl"),
+    include_str!("../../shaders-generated/render_atlas.wgsl"),
+    include_str!("../../shaders-generated/render_compact.wgsl"),
+    include_str!("../../shaders-generated/render_atlas_compact.wgsl"),
+    include_str!("../../shaders-generated/render_no_aovs.wgsl"),
+    include_str!("../../shaders-generated/render_atlas_no_aovs.wgsl"),
+    include_str!("../../shaders-generated/render_compact_no_aovs.wgsl"),
+    include_str!("../../shaders-generated/render_atlas_compact_no_aovs.wgsl"),
+];
 
 pub struct Renderer<'a> {
     context: &'a RenderContext,
     width: u32,
-    height: u32,
+    tile_width: u32,
+    tile_height: u32,
+    tiles: Vec<Tile>,
     samples: u32,
+    completed: u32,
+    sample_offset: u32,
+    sample_index: u32,
+    checkpoint_path: Option<String>,
+    checkpoint_interval: u32,
     target_bind_group_layout: wgpu::BindGroupLayout,
     pipeline: wgpu::ComputePipeline,
+    push_constants: PushConstants,
+    // The constants of every sample are written before its submission
+    constants_buffer: Option<wgpu::Buffer>,
     render_target: wgpu::Texture,
+    // The AOV targets are only bound if any AOVs are requested
+    aov_targets: Option<[wgpu::Texture; 4]>,
+    // The rays traced by all tiles, which is only counted if the statistics are collected
+    ray_count_buffer: wgpu::Buffer,
+    collect_stats: bool,
+    // The time of each sample, which is only measured if the statistics are collected
+    sample_times: Vec<Duration>,
 }
 
 pub struct BindGroupLayoutSet {
@@ -21,6 +72,8 @@ pub struct BindGroupLayoutSet {
     pub primitive: wgpu::BindGroupLayout,
     pub material: wgpu::BindGroupLayout,
     pub texture: wgpu::BindGroupLayout,
+    // The storage arrays which the shaders declare by their chunks
+    pub arrays: Vec<StorageArrayLayout>,
 }
 
 pub struct BindGroupSet {
@@ -33,28 +86,56 @@ pub struct BindGroupSet {
 impl<'a> Renderer<'a> {
     pub fn new(
         context: &'a RenderContext,
-        config: &Config,
+        settings: &RenderSettings,
         bind_group_layout_set: BindGroupLayoutSet,
-    ) -> Self {
+    ) -> Result<Self, Error> {
         let device = context.device();
 
-        let Size { width, height } = config.size;
-        let width = (width + 15) / 16 * 16;
-        let height = (height + 15) / 16 * 16;
+        let width = settings.size.width;
+        let tile_size = Tile::max_size(context, settings);
+
+        let push_constants = PushConstants::new(context, 24);
+        // The ray counter is the only storage buffer besides those of the scene
+        context.check_limits(1)?;
 
+        let aovs = settings.renders_aovs();
+        // The render target is followed by the AOV targets
+        let aov_formats = match aovs {
+            true => &AOV_FORMATS[..],
+            false => &[],
+        };
+        let target_entries: Vec<_> = std::iter::once(&wgpu::TextureFormat::Rgba32Float)
+            .chain(aov_formats)
+            .enumerate()
+            .map(|(i, format)| wgpu::BindGroupLayoutEntry {
+                binding: i as u32,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: match format {
+                        wgpu::TextureFormat::Rgba32Uint => wgpu::StorageTextureAccess::WriteOnly,
+                        _ => wgpu::StorageTextureAccess::ReadWrite,
+                    },
+                    format: *format,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            })
+            .chain(std::iter::once(wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }))
+            .chain(push_constants.layout_entry())
+            .collect();
         let target_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 label: None,
-                entries: &[wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::ReadWrite,
-                        format: wgpu::TextureFormat::Rgba32Float,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                }],
+                entries: &target_entries,
             });
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -66,107 +147,383 @@ impl<'a> Renderer<'a> {
                 &bind_group_layout_set.material,
                 &bind_group_layout_set.texture,
             ],
-            push_constant_ranges: &[wgpu::PushConstantRange {
-                stages: wgpu::ShaderStages::COMPUTE,
-                range: 0..4,
-            }],
+            push_constant_ranges: &push_constants.ranges(),
         });
 
-        let shader_module =
-            device.create_shader_module(wgpu::include_wgsl!("../../shaders-generated/render.wgsl"));
-
         let mut constants = HashMap::new();
-        constants.insert(String::from("MAX_DEPTH"), config.depth as f64);
+        constants.insert(String::from("MAX_DEPTH"), settings.depth as f64);
+        constants.insert(String::from("AOVS"), aovs as u32 as f64);
+        constants.insert(String::from("STATS"), settings.collect_stats as u32 as f64);
 
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            module: &shader_module,
-            entry_point: "main",
-            compilation_options: wgpu::PipelineCompilationOptions {
-                constants: &constants,
-                zero_initialize_workgroup_memory: true,
-                vertex_pulling_transform: false,
-            },
-            cache: None,
-        });
+        let pipeline = context.validate("render", |device| {
+            let atlas = textures::uses_atlas(context, settings);
+            let compact = settings.geometry_layout == GeometryLayout::Compact;
+            let variant = atlas as usize | (compact as usize) << 1 | (!aovs as usize) << 2;
+            let shader = wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(RENDER_SHADERS[variant].into()),
+            };
+            let shader = StorageArray::shader(&bind_group_layout_set.arrays, shader);
+            let shader_module = device.create_shader_module(push_constants.shader(shader));
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: None,
+                layout: Some(&pipeline_layout),
+                module: &shader_module,
+                entry_point: "main",
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    zero_initialize_workgroup_memory: true,
+                    vertex_pulling_transform: false,
+                },
+                cache: None,
+            })
+        })?;
 
-        let render_target = device.create_texture(&wgpu::TextureDescriptor {
+        let render_target = create_target(context, &tile_size, wgpu::TextureFormat::Rgba32Float);
+        let aov_targets =
+            aovs.then(|| AOV_FORMATS.map(|format| create_target(context, &tile_size, format)));
+        let constants_buffer = push_constants.create_buffer(device, &[0; 24]);
+        // The count is zero-initialized and held in two 32-bit words
+        let ray_count_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::STORAGE_BINDING,
-            view_formats: &[],
+            size: 8,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
         });
 
-        Self {
+        Ok(Self {
             context,
             width,
-            height,
-            samples: config.samples,
+            tile_width: tile_size.width,
+            tile_height: tile_size.height,
+            tiles: Tile::split(&Tile::region(settings), &tile_size),
+            samples: settings.samples,
+            completed: 0,
+            sample_offset: settings.sample_offset,
+            sample_index: settings.sample_offset,
+            checkpoint_path: settings.checkpoint.clone(),
+            checkpoint_interval: settings.checkpoint_interval.max(1),
             target_bind_group_layout,
             pipeline,
+            push_constants,
+            constants_buffer,
             render_target,
+            aov_targets,
+            ray_count_buffer,
+            collect_stats: settings.collect_stats,
+            sample_times: Vec::new(),
+        })
+    }
+
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    pub fn resume(&mut self, checkpoint: &Checkpoint, hash: u64) -> Result<(), Error> {
+        checkpoint.check(hash, &self.tiles[0])?;
+        checkpoint.write_texture(self.context, &self.render_target);
+
+        self.completed = checkpoint.samples;
+        self.sample_offset = checkpoint.sample_offset;
+        self.sample_index = checkpoint.sample_index;
+        Ok(())
+    }
+
+    // Discards the accumulated samples before rendering the next tile
+    pub fn clear(&mut self) {
+        let aov_targets = self.aov_targets.iter().flatten();
+        for target in std::iter::once(&self.render_target).chain(aov_targets) {
+            let bytes_per_pixel = target.format().block_copy_size(None).unwrap();
+            let zeros = vec![0; (self.tile_width * self.tile_height * bytes_per_pixel) as usize];
+            self.context.queue().write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: target,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                &zeros,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.tile_width * bytes_per_pixel),
+                    rows_per_image: None,
+                },
+                wgpu::Extent3d {
+                    width: self.tile_width,
+                    height: self.tile_height,
+                    depth_or_array_layers: 1,
+                },
+            );
         }
+
+        self.completed = 0;
+        self.sample_index = self.sample_offset;
     }
 
-    pub fn render(&self, bind_group_set: BindGroupSet) {
+    pub fn render(
+        &mut self,
+        bind_group_set: &BindGroupSet,
+        tile: &Tile,
+        hash: u64,
+    ) -> Result<(), Error> {
         let device = self.context.device();
         let queue = self.context.queue();
 
-        let view = self
-            .render_target
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let aov_targets = self.aov_targets.iter().flatten();
+        let views: Vec<_> = std::iter::once(&self.render_target)
+            .chain(aov_targets)
+            .map(|target| target.create_view(&wgpu::TextureViewDescriptor::default()))
+            .collect();
+        let entries: Vec<_> = views
+            .iter()
+            .enumerate()
+            .map(|(i, view)| wgpu::BindGroupEntry {
+                binding: i as u32,
+                resource: wgpu::BindingResource::TextureView(view),
+            })
+            .chain(std::iter::once(wgpu::BindGroupEntry {
+                binding: 5,
+                resource: self.ray_count_buffer.as_entire_binding(),
+            }))
+            .chain(PushConstants::bind_group_entry(&self.constants_buffer))
+            .collect();
 
         let output_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &self.target_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view),
-            }],
+            entries: &entries,
         });
 
         let progress_bar = Arc::new(ProgressBar::new(self.samples as u64));
+        progress_bar.set_position(self.completed.min(self.samples) as u64);
+
+        while self.completed < self.samples {
+            // Without checkpoints every sample is submitted at once
+            let batch = match self.checkpoint_path {
+                Some(_) => self.checkpoint_interval.min(self.samples - self.completed),
+                None => self.samples - self.completed,
+            };
+
+            for sample in self.sample_index..self.sample_index + batch {
+                let mut encoder =
+                    device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
 
-        for sample in 0..self.samples {
-            let mut encoder =
-                device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
-
-            {
-                let sample: [u8; 4] = sample.to_ne_bytes();
-
-                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                    label: None,
-                    timestamp_writes: None,
-                });
-                compute_pass.set_pipeline(&self.pipeline);
-                compute_pass.set_bind_group(0, &output_bind_group, &[]);
-                compute_pass.set_bind_group(1, &bind_group_set.scene, &[]);
-                compute_pass.set_bind_group(2, &bind_group_set.primitive, &[]);
-                compute_pass.set_bind_group(3, &bind_group_set.material, &[]);
-                compute_pass.set_bind_group(4, &bind_group_set.texture, &[]);
-                compute_pass.set_push_constants(0, &sample);
-                compute_pass.dispatch_workgroups(self.width / 16, self.height / 16, 1);
+                {
+                    // The tile origin offsets the pixels traced by the camera into the full image
+                    let constants: Vec<u8> =
+                        [sample, self.width, tile.x, tile.y, tile.width, tile.height]
+                            .iter()
+                            .flat_map(|constant| constant.to_ne_bytes())
+                            .collect();
+                    if let Some(buffer) = &self.constants_buffer {
+                        queue.write_buffer(buffer, 0, &constants);
+                    }
+
+                    let mut compute_pass =
+                        encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                            label: None,
+                            timestamp_writes: None,
+                        });
+                    compute_pass.set_pipeline(&self.pipeline);
+                    compute_pass.set_bind_group(0, &output_bind_group, &[]);
+                    compute_pass.set_bind_group(1, &bind_group_set.scene, &[]);
+                    compute_pass.set_bind_group(2, &bind_group_set.primitive, &[]);
+                    compute_pass.set_bind_group(3, &bind_group_set.material, &[]);
+                    compute_pass.set_bind_group(4, &bind_group_set.texture, &[]);
+                    self.push_constants.set(&mut compute_pass, &constants);
+                    compute_pass.dispatch_workgroups(
+                        tile.width.div_ceil(16),
+                        tile.height.div_ceil(16),
+                        1,
+                    );
+                }
+
+                let progress_bar = progress_bar.clone();
+                let start = Instant::now();
+                let submission = queue.submit(Some(encoder.finish()));
+                queue.on_submitted_work_done(move || progress_bar.inc(1));
+                // Timing a sample waits for it, which keeps the GPU from overlapping the samples
+                if self.collect_stats {
+                    device.poll(wgpu::Maintain::wait_for(submission));
+                    self.sample_times.push(start.elapsed());
+                }
             }
 
-            let progress_bar = progress_bar.clone();
-            queue.submit(Some(encoder.finish()));
-            queue.on_submitted_work_done(move || progress_bar.inc(1));
+            device.poll(wgpu::Maintain::Wait);
+            self.completed += batch;
+            self.sample_index += batch;
+
+            if let Some(path) = &self.checkpoint_path {
+                self.checkpoint(hash)?.save(path)?;
+            }
         }
 
-        device.poll(wgpu::Maintain::Wait);
         progress_bar.finish_and_clear();
+        Ok(())
+    }
+
+    pub fn checkpoint(&self, hash: u64) -> Result<Checkpoint, Error> {
+        let tile = self.tiles[0];
+        Ok(Checkpoint {
+            width: tile.width,
+            height: tile.height,
+            samples: self.completed,
+            sample_offset: self.sample_offset,
+            sample_index: self.sample_index,
+            hash,
+            data: self.read_accumulation(&tile)?,
+        })
+    }
+
+    // Reads back the summed samples of the tile, which is the last one rendered
+    pub fn read_accumulation(&self, tile: &Tile) -> Result<Vec<f32>, Error> {
+        let data = self.read_target(&self.render_target, tile)?;
+        Ok(data
+            .chunks_exact(4)
+            .map(|chunk| f32::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    pub fn read_aovs(&self, tile: &Tile) -> Result<Option<Aovs>, Error> {
+        let Some([albedo, normal, position, id]) = &self.aov_targets else {
+            return Ok(None);
+        };
+        Ok(Some(Aovs::from_bytes(
+            tile.width,
+            tile.height,
+            &self.read_target(albedo, tile)?,
+            &self.read_target(normal, tile)?,
+            &self.read_target(position, tile)?,
+            &self.read_target(id, tile)?,
+        )))
+    }
+
+    fn read_target(&self, target: &wgpu::Texture, tile: &Tile) -> Result<Vec<u8>, Error> {
+        let device = self.context.device();
+        let queue = self.context.queue();
+
+        // Rows of a texture copy must be aligned to 256 bytes
+        let bytes_per_row = tile.width * target.format().block_copy_size(None).unwrap();
+        let padded_bytes_per_row = bytes_per_row.next_multiple_of(256);
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: (padded_bytes_per_row * tile.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: target,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &staging_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width: tile.width,
+                height: tile.height,
+                depth_or_array_layers: 1,
+            },
+        );
+
+        queue.submit(Some(encoder.finish()));
+
+        let (tx, rx) = mpsc::channel();
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        rx.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let mut data = Vec::with_capacity((bytes_per_row * tile.height) as usize);
+        {
+            let view = slice.get_mapped_range();
+            for row in view.chunks_exact(padded_bytes_per_row as usize) {
+                data.extend_from_slice(&row[..bytes_per_row as usize]);
+            }
+        }
+        staging_buffer.unmap();
+
+        Ok(data)
+    }
+
+    // The number of rays traced by all tiles, which is only counted if the statistics are collected
+    pub fn read_ray_count(&self) -> Result<u64, Error> {
+        let device = self.context.device();
+        let queue = self.context.queue();
+
+        let staging_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 8,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.copy_buffer_to_buffer(&self.ray_count_buffer, 0, &staging_buffer, 0, 8);
+        queue.submit(Some(encoder.finish()));
+
+        let (tx, rx) = mpsc::channel();
+        let slice = staging_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, move |result| tx.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait).panic_on_timeout();
+        rx.recv().unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let count = {
+            let view = slice.get_mapped_range();
+            let [low, high] = [&view[..4], &view[4..]]
+                .map(|word| u32::from_ne_bytes(word.try_into().unwrap()) as u64);
+            high << 32 | low
+        };
+        staging_buffer.unmap();
+        Ok(count)
+    }
+
+    pub fn sample_times(&self) -> &[Duration] {
+        &self.sample_times
+    }
+
+    // The number of samples accumulated in the render target
+    pub fn completed_samples(&self) -> u32 {
+        self.completed
     }
 
     pub fn render_target(&self) -> &wgpu::Texture {
         &self.render_target
     }
+
+    pub fn aov_targets(&self) -> Option<&[wgpu::Texture; 4]> {
+        self.aov_targets.as_ref()
+    }
+}
+
+fn create_target(
+    context: &RenderContext,
+    size: &Size,
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    context.device().create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
 }
@@ -0,0 +1,299 @@
+use std::borrow::Cow;
+
+use encase::{internal::WriteInto, ShaderSize, ShaderType, StorageBuffer};
+
+use crate::error::Error;
+
+use super::RenderContext;
+
+// The directive declaring an array in the shaders, e.g.
+// `///#chunked @group(1) @binding(1) triangles: array<Triangle>`
+const CHUNKED_PREFIX: &str = "///#chunked";
+// The bindings between the chunks of an array, which leaves room for the other bindings of the group
+const CHUNK_BINDING_STRIDE: u32 = 16;
+
+// A storage array which may be larger than the device can bind, and is split into chunks of
+// bindings otherwise. The shaders read it through a function named like the array.
+pub struct StorageArray {
+    layout: StorageArrayLayout,
+    binding: u32,
+    buffers: Vec<wgpu::Buffer>,
+}
+
+// What the shaders need to know of an array to declare its chunks
+#[derive(Clone, Copy)]
+pub struct StorageArrayLayout {
+    name: &'static str,
+    chunks: usize,
+    chunk_len: usize,
+}
+
+impl StorageArray {
+    pub fn try_new<T: ShaderType + ShaderSize + WriteInto>(
+        context: &RenderContext,
+        buffer: &'static str,
+        name: &'static str,
+        binding: u32,
+        data: &[T],
+    ) -> Result<Self, Error> {
+        let device = context.device();
+        let queue = context.queue();
+
+        // A single element has to fit, and at least one chunk is bound even if the array is empty
+        let stride = T::SHADER_SIZE.get();
+        let chunk_len = (context.max_binding_size() / stride).max(1) as usize;
+        let chunks: Vec<_> = match data.is_empty() {
+            true => vec![data],
+            false => data.chunks(chunk_len).collect(),
+        };
+
+        let mut buffers = Vec::with_capacity(chunks.len());
+        let mut size = 0;
+        for chunk in &chunks {
+            let mut wgsl_bytes = StorageBuffer::new(Vec::new());
+            wgsl_bytes.write(*chunk)?;
+            let wgsl_bytes = wgsl_bytes.into_inner();
+            size += wgsl_bytes.len() as u64;
+
+            let chunk_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size: wgsl_bytes.len() as u64,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            });
+            queue.write_buffer(&chunk_buffer, 0, &wgsl_bytes);
+            buffers.push(chunk_buffer);
+        }
+        context.add_storage_buffer(buffer, size, buffers.len() as u32);
+
+        Ok(Self {
+            layout: StorageArrayLayout {
+                name,
+                chunks: buffers.len(),
+                chunk_len,
+            },
+            binding,
+            buffers,
+        })
+    }
+
+    pub fn layout(&self) -> StorageArrayLayout {
+        self.layout
+    }
+
+    pub fn layout_entries(&self) -> impl Iterator<Item = wgpu::BindGroupLayoutEntry> + '_ {
+        (0..self.buffers.len()).map(|chunk| wgpu::BindGroupLayoutEntry {
+            binding: self.binding + chunk as u32 * CHUNK_BINDING_STRIDE,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        })
+    }
+
+    pub fn bind_group_entries(&self) -> impl Iterator<Item = wgpu::BindGroupEntry<'_>> {
+        self.buffers
+            .iter()
+            .enumerate()
+            .map(|(chunk, buffer)| wgpu::BindGroupEntry {
+                binding: self.binding + chunk as u32 * CHUNK_BINDING_STRIDE,
+                resource: buffer.as_entire_binding(),
+            })
+    }
+
+    // Replaces the directives of the arrays with a binding per chunk and the function reading
+    // the element at an index from its chunk
+    pub fn shader<'a>(
+        layouts: &[StorageArrayLayout],
+        shader: wgpu::ShaderModuleDescriptor<'a>,
+    ) -> wgpu::ShaderModuleDescriptor<'a> {
+        let wgpu::ShaderSource::Wgsl(source) = shader.source else {
+            return shader;
+        };
+        let mut result = String::with_capacity(source.len());
+        for line in source.lines() {
+            match line.strip_prefix(CHUNKED_PREFIX).and_then(|directive| {
+                let declaration = Declaration::parse(directive)?;
+                let layout = layouts
+                    .iter()
+                    .find(|layout| layout.name == declaration.name)?;
+                Some(declaration.expand(layout))
+            }) {
+                Some(declarations) => result.push_str(&declarations),
+                None => {
+                    result.push_str(line);
+                    result.push('\n');
+                }
+            }
+        }
+        wgpu::ShaderModuleDescriptor {
+            label: shader.label,
+            source: wgpu::ShaderSource::Wgsl(Cow::Owned(result)),
+        }
+    }
+}
+
+// The directive of an array, which names its first binding and the type of its elements
+struct Declaration<'a> {
+    group: u32,
+    binding: u32,
+    name: &'a str,
+    element: &'a str,
+}
+
+impl<'a> Declaration<'a> {
+    fn parse(directive: &'a str) -> Option<Self> {
+        let mut tokens = directive.split_whitespace();
+        let mut attribute = |name: &str| -> Option<u32> {
+            let token = tokens.next()?.strip_prefix(name)?;
+            token.strip_prefix('(')?.strip_suffix(')')?.parse().ok()
+        };
+        let group = attribute("@group")?;
+        let binding = attribute("@binding")?;
+        let name = tokens.next()?.strip_suffix(':')?;
+        let element = tokens.next()?.strip_prefix("array<")?.strip_suffix('>')?;
+        Some(Self {
+            group,
+            binding,
+            name,
+            element,
+        })
+    }
+
+    fn expand(&self, layout: &StorageArrayLayout) -> String {
+        let Self {
+            group,
+            binding,
+            name,
+            element,
+        } = self;
+        let mut result = String::new();
+        for chunk in 0..layout.chunks {
+            let binding = binding + chunk as u32 * CHUNK_BINDING_STRIDE;
+            result += &format!("@group({group}) @binding({binding})\n");
+            result += &format!("var<storage, read> {name}_{chunk}: array<{element}>;\n\n");
+        }
+
+        let last = layout.chunks - 1;
+        result += &format!("fn {name}(i: u32) -> {element} {{\n");
+        if last == 0 {
+            result += &format!("    return {name}_0[i];\n");
+        } else {
+            let chunk_len = layout.chunk_len;
+            result += &format!("    switch i / {chunk_len}u {{\n");
+            for chunk in 0..last {
+                let start = chunk * chunk_len;
+                result +=
+                    &format!("        case {chunk}u: {{ return {name}_{chunk}[i - {start}u]; }}\n");
+            }
+            let start = last * chunk_len;
+            result += &format!("        default: {{ return {name}_{last}[i - {start}u]; }}\n");
+            result += "    }\n";
+        }
+        result += "}\n";
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIRECTIVE: &str = " @group(1) @binding(2) triangles: array<Triangle>";
+
+    fn layout(chunks: usize, chunk_len: usize) -> StorageArrayLayout {
+        StorageArrayLayout {
+            name: "triangles",
+            chunks,
+            chunk_len,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let declaration = Declaration::parse(DIRECTIVE).unwrap();
+        assert_eq!(declaration.group, 1);
+        assert_eq!(declaration.binding, 2);
+        assert_eq!(declaration.name, "triangles");
+        assert_eq!(declaration.element, "Triangle");
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for directive in [
+            "",
+            " @group(1) triangles: array<Triangle>",
+            " @group(1) @binding(two) triangles: array<Triangle>",
+            " @binding(2) @group(1) triangles: array<Triangle>",
+            " @group(1) @binding(2) triangles array<Triangle>",
+            " @group(1) @binding(2) triangles: Triangle",
+        ] {
+            assert!(Declaration::parse(directive).is_none(), "{directive}");
+        }
+    }
+
+    #[test]
+    fn expand_single_chunk() {
+        let declaration = Declaration::parse(DIRECTIVE).unwrap();
+        assert_eq!(
+            declaration.expand(&layout(1, 100)),
+            "@group(1) @binding(2)\n\
+             var<storage, read> triangles_0: array<Triangle>;\n\n\
+             fn triangles(i: u32) -> Triangle {\n    \
+             return triangles_0[i];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn expand_chunks() {
+        let declaration = Declaration::parse(DIRECTIVE).unwrap();
+        assert_eq!(
+            declaration.expand(&layout(3, 100)),
+            "@group(1) @binding(2)\n\
+             var<storage, read> triangles_0: array<Triangle>;\n\n\
+             @group(1) @binding(18)\n\
+             var<storage, read> triangles_1: array<Triangle>;\n\n\
+             @group(1) @binding(34)\n\
+             var<storage, read> triangles_2: array<Triangle>;\n\n\
+             fn triangles(i: u32) -> Triangle {\n    \
+             switch i / 100u {\n        \
+             case 0u: { return triangles_0[i - 0u]; }\n        \
+             case 1u: { return triangles_1[i - 100u]; }\n        \
+             default: { return triangles_2[i - 200u]; }\n    \
+             }\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn shader() {
+        let source = format!(
+            "{CHUNKED_PREFIX}{DIRECTIVE}\n\
+             {CHUNKED_PREFIX} @group(1) @binding(3) edges: array<Edges>\n\
+             fn main() {{}}\n"
+        );
+        let shader = StorageArray::shader(
+            &[layout(2, 8)],
+            wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::ShaderSource::Wgsl(Cow::Owned(source)),
+            },
+        );
+        let wgpu::ShaderSource::Wgsl(result) = shader.source else {
+            unreachable!();
+        };
+        let declaration = Declaration::parse(DIRECTIVE).unwrap();
+        // The directives of arrays without a layout are left as comments
+        assert_eq!(
+            result,
+            format!(
+                "{}{CHUNKED_PREFIX} @group(1) @binding(3) edges: array<Edges>\nfn main() {{}}\n",
+                declaration.expand(&layout(2, 8))
+            )
+        );
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    config::{RegionOutput, Size},
+    settings::RenderSettings,
+};
+
+use super::RenderContext;
+
+// The tile size used when the image exceeds the texture size limit of the device
+const DEFAULT_TILE_SIZE: u32 = 2048;
+
+#[derive(Clone, Copy)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    // The window of the image to be rendered, which is clamped to the image
+    pub fn region(settings: &RenderSettings) -> Tile {
+        let Size { width, height } = settings.size;
+        let Some(region) = &settings.region else {
+            return Tile {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            };
+        };
+
+        let (scale_x, scale_y) = if region.normalized {
+            (width as f32, height as f32)
+        } else {
+            (1.0, 1.0)
+        };
+        let x = ((region.x * scale_x).round() as u32).min(width - 1);
+        let y = ((region.y * scale_y).round() as u32).min(height - 1);
+        Tile {
+            x,
+            y,
+            width: ((region.width * scale_x).round() as u32).clamp(1, width - x),
+            height: ((region.height * scale_y).round() as u32).clamp(1, height - y),
+        }
+    }
+
+    // The window of the image covered by the output, which is the full image unless the region is
+    // cropped
+    pub fn window(settings: &RenderSettings) -> Tile {
+        let Size { width, height } = settings.size;
+        match settings.region_output {
+            RegionOutput::Crop => Self::region(settings),
+            RegionOutput::Black | RegionOutput::Transparent => Tile {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        }
+    }
+
+    pub fn max_size(context: &RenderContext, settings: &RenderSettings) -> Size {
+        let region = Self::region(settings);
+        let max_dimension = context.device().limits().max_texture_dimension_2d;
+
+        let tile_size = match settings.tile_size {
+            Some(tile_size) => tile_size.clamp(1, max_dimension),
+            None if region.width > max_dimension || region.height > max_dimension => {
+                DEFAULT_TILE_SIZE
+            }
+            None => max_dimension,
+        };
+        Size::new(region.width.min(tile_size), region.height.min(tile_size))
+    }
+
+    pub fn split(region: &Tile, tile_size: &Size) -> Vec<Tile> {
+        let mut tiles = Vec::new();
+        for y in (0..region.height).step_by(tile_size.height as usize) {
+            for x in (0..region.width).step_by(tile_size.width as usize) {
+                tiles.push(Tile {
+                    x: region.x + x,
+                    y: region.y + y,
+                    width: tile_size.width.min(region.width - x),
+                    height: tile_size.height.min(region.height - y),
+                });
+            }
+        }
+        tiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tile(x: u32, y: u32, width: u32, height: u32) -> Tile {
+        Tile {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    fn bounds(tiles: &[Tile]) -> Vec<[u32; 4]> {
+        tiles
+            .iter()
+            .map(|tile| [tile.x, tile.y, tile.width, tile.height])
+            .collect()
+    }
+
+    #[test]
+    fn split_into_single_tile() {
+        let tiles = Tile::split(&tile(3, 5, 64, 32), &Size::new(64, 32));
+        assert_eq!(bounds(&tiles), [[3, 5, 64, 32]]);
+    }
+
+    #[test]
+    fn split_with_partial_tiles() {
+        let tiles = Tile::split(&tile(10, 20, 100, 50), &Size::new(40, 40));
+        assert_eq!(
+            bounds(&tiles),
+            [
+                [10, 20, 40, 40],
+                [50, 20, 40, 40],
+                [90, 20, 20, 40],
+                [10, 60, 40, 10],
+                [50, 60, 40, 10],
+                [90, 60, 20, 10],
+            ]
+        );
+    }
+
+    #[test]
+    fn split_covers_region() {
+        let region = tile(0, 0, 97, 61);
+        let tiles = Tile::split(&region, &Size::new(16, 16));
+        assert_eq!(tiles.len(), 7 * 4);
+        let area: u32 = tiles.iter().map(|tile| tile.width * tile.height).sum();
+        assert_eq!(area, region.width * region.height);
+    }
+
+    #[test]
+    fn region_clamped_to_image() {
+        let mut settings = RenderSettings {
+            size: Size::new(100, 50),
+            ..RenderSettings::default()
+        };
+        settings.region = Some("80x80+60+10".parse().unwrap());
+        assert_eq!(bounds(&[Tile::region(&settings)]), [[60, 10, 40, 40]]);
+        settings.region = Some("0.5x0.5+1.0+1.0".parse().unwrap());
+        assert_eq!(bounds(&[Tile::region(&settings)]), [[99, 49, 1, 1]]);
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use encase::ShaderType;
 
 use crate::{
@@ -75,13 +77,30 @@ pub struct FlatBvhNode {
 
 pub struct Bvh {
     root: Box<BvhNode>,
+    build_time: Duration,
+}
+
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    // The most triangles of a leaf, which are tested one by one
+    pub max_leaf_triangles: u32,
+    // The expected cost of tracing a ray in units of a triangle test, which counts a node
+    // traversal as one test
+    pub sah_cost: f32,
+    pub build_time: Duration,
 }
 
 impl Bvh {
     pub fn new(primitives: &Primitives, triangles: &mut [Triangle]) -> Self {
+        let start = Instant::now();
         let len = triangles.len();
         let root = Box::new(BvhNode::new(primitives, triangles, 0, len));
-        Self { root }
+        Self {
+            root,
+            build_time: start.elapsed(),
+        }
     }
 
     pub fn flatten(&self) -> Vec<FlatBvhNode> {
@@ -90,6 +109,40 @@ impl Bvh {
         nodes
     }
 
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            nodes: 0,
+            leaves: 0,
+            depth: 0,
+            max_leaf_triangles: 0,
+            sah_cost: 0.0,
+            build_time: self.build_time,
+        };
+        Self::count_node(&self.root, 1, &mut stats);
+        // The probability of a ray hitting a node is its surface area relative to the root
+        stats.sah_cost /= self.root.aabb.surface_area();
+        stats
+    }
+
+    fn count_node(node: &BvhNode, depth: usize, stats: &mut BvhStats) {
+        stats.nodes += 1;
+        stats.depth = stats.depth.max(depth);
+        let area = node.aabb.surface_area();
+        match (&node.left, &node.right) {
+            (Some(left), Some(right)) => {
+                stats.sah_cost += area;
+                Self::count_node(left, depth + 1, stats);
+                Self::count_node(right, depth + 1, stats);
+            }
+            _ => {
+                stats.leaves += 1;
+                let triangles = node.triangle_end - node.triangle_start;
+                stats.max_leaf_triangles = stats.max_leaf_triangles.max(triangles);
+                stats.sah_cost += area * triangles as f32;
+            }
+        }
+    }
+
     fn flatten_node(node: &BvhNode, nodes: &mut Vec<FlatBvhNode>) {
         let curr_idx = nodes.len();
         nodes.push(FlatBvhNode {
@@ -0,0 +1,304 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::{self, Display},
+};
+
+use glam::Mat4;
+use gltf::{camera::Projection, khr_lights_punctual::Kind, mesh::Mode};
+use itertools::Itertools;
+
+use crate::{
+    error::{describe, Error},
+    materials::Materials,
+    primitives::Primitives,
+    textures,
+};
+
+use super::{
+    bvh::{Bvh, BvhStats},
+    camera_builder, image_name, walk, Transform,
+};
+
+// What the loader makes of a glTF document, gathered without a device
+pub struct Report {
+    // The nodes of the first scene, indented by their depth
+    pub nodes: Vec<String>,
+    pub meshes: Vec<String>,
+    pub materials: Vec<String>,
+    pub images: Vec<String>,
+    pub cameras: Vec<String>,
+    pub lights: Vec<String>,
+    // The instances of the primitives and their triangles in the scene
+    pub primitives: usize,
+    pub triangles: usize,
+    pub bvh: Option<BvhStats>,
+    // The problems which fail the render
+    pub errors: Vec<Error>,
+    // The parts of the document which the renderer ignores
+    pub warnings: Vec<String>,
+}
+
+// Walks the first scene like the loader, but collects all problems instead of failing at the first
+pub fn inspect(scene_path: &str) -> Result<Report, Error> {
+    let (document, buffers, images) = gltf::import(scene_path).map_err(|source| Error::Gltf {
+        path: scene_path.to_string(),
+        source,
+    })?;
+    let mut report = Report {
+        nodes: Vec::new(),
+        meshes: Vec::new(),
+        materials: Vec::new(),
+        images: Vec::new(),
+        cameras: Vec::new(),
+        lights: Vec::new(),
+        primitives: 0,
+        triangles: 0,
+        bvh: None,
+        errors: Vec::new(),
+        warnings: Vec::new(),
+    };
+
+    for (image, data) in document.images().zip(&images) {
+        let name = image_name(&image);
+        let [width, height] = [data.width, data.height];
+        let format = data.format;
+        report
+            .images
+            .push(format!("{name}: {format:?}, {width}x{height}"));
+        if let Err(error) = textures::to_rgba8(&name, data) {
+            report.errors.push(error);
+        }
+    }
+
+    for mesh in document.meshes() {
+        let triangles: usize = mesh
+            .primitives()
+            .map(|primitive| primitive.indices().map_or(0, |indices| indices.count() / 3))
+            .sum();
+        report.meshes.push(format!(
+            "{}: {} primitives, {triangles} triangles",
+            describe(mesh.name(), mesh.index()),
+            mesh.primitives().len()
+        ));
+    }
+
+    for material in document.materials() {
+        let texture = |info: Option<gltf::texture::Texture>| {
+            info.map_or(String::from("none"), |texture| {
+                image_name(&texture.source())
+            })
+        };
+        let pbr_metallic_roughness = material.pbr_metallic_roughness();
+        let textures = [
+            (
+                "base color",
+                texture(
+                    pbr_metallic_roughness
+                        .base_color_texture()
+                        .map(|info| info.texture()),
+                ),
+            ),
+            (
+                "metallic-roughness",
+                texture(
+                    pbr_metallic_roughness
+                        .metallic_roughness_texture()
+                        .map(|info| info.texture()),
+                ),
+            ),
+            (
+                "normal",
+                texture(material.normal_texture().map(|info| info.texture())),
+            ),
+            (
+                "emissive",
+                texture(material.emissive_texture().map(|info| info.texture())),
+            ),
+        ];
+        let mut line = format!(
+            "{}: {}",
+            // Materials of the document always have an index
+            describe(material.name(), material.index().unwrap()),
+            textures
+                .iter()
+                .map(|(kind, image)| format!("{kind} {image}"))
+                .join(", ")
+        );
+        if let Some(extensions) = material.extensions().filter(|map| !map.is_empty()) {
+            line += &format!(", extensions {}", extensions.keys().join(" "));
+        }
+        if material.emissive_factor() != [0.0; 3] {
+            line += ", emissive";
+        }
+        report.materials.push(line);
+    }
+
+    let Some(scene) = document.scenes().next() else {
+        let message = String::from("the document has no scenes");
+        report.errors.push(Error::Scene(message));
+        return Ok(report);
+    };
+    if document.scenes().len() > 1 {
+        let scenes = document.scenes().len();
+        let message = format!("only the first of the {scenes} scenes is rendered");
+        report.warnings.push(message);
+    }
+
+    let mut parents = HashMap::new();
+    for node in document.nodes() {
+        for child in node.children() {
+            parents.insert(child.index(), node.index());
+        }
+    }
+    let depth = |mut index| {
+        let mut depth = 0;
+        while let Some(parent) = parents.get(&index) {
+            index = *parent;
+            depth += 1;
+        }
+        depth
+    };
+
+    let mut primitives = Primitives::new();
+    let mut triangles = Vec::new();
+    // The materials are checked once, even if several primitives use them
+    let mut materials = BTreeMap::new();
+    for node in scene.nodes() {
+        walk(node, &Mat4::IDENTITY, &mut |node, transform_matrix| {
+            let name = describe(node.name(), node.index());
+            let mut parts = Vec::new();
+
+            if let Some(mesh) = node.mesh() {
+                parts.push(format!("mesh {}", describe(mesh.name(), mesh.index())));
+                let transform = Transform::new(*transform_matrix);
+                for primitive in mesh.primitives() {
+                    report.primitives += 1;
+                    materials.insert(primitive.material().index(), primitive.material());
+                    if primitive.mode() != Mode::Triangles {
+                        report.warnings.push(format!(
+                            "primitive {} of node {name} is drawn as {:?}, but is read as triangles",
+                            primitive.index(),
+                            primitive.mode()
+                        ));
+                    }
+                    match primitives.add(&buffers, node, &primitive, &transform, 0) {
+                        Ok(mut primitive_triangles) => triangles.append(&mut primitive_triangles),
+                        Err(error) => report.errors.push(error),
+                    }
+                }
+            }
+
+            if let Some(camera) = node.camera() {
+                parts.push(format!(
+                    "camera {}",
+                    describe(camera.name(), camera.index())
+                ));
+                let projection = match camera.projection() {
+                    Projection::Perspective(perspective) => format!(
+                        "perspective with a vertical field of view of {:.1}°",
+                        perspective.yfov().to_degrees()
+                    ),
+                    Projection::Orthographic(_) => String::from("orthographic"),
+                };
+                report.cameras.push(format!("{name}: {projection}"));
+                if let Err(error) = camera_builder(node, &camera, transform_matrix) {
+                    report.errors.push(error);
+                }
+            }
+
+            if let Some(light) = node.light() {
+                parts.push(format!("light {}", describe(light.name(), light.index())));
+                let kind = match light.kind() {
+                    Kind::Directional => "directional",
+                    Kind::Point => "point",
+                    Kind::Spot { .. } => "spot",
+                };
+                report.lights.push(format!("{name}: {kind}"));
+                report.warnings.push(format!(
+                    "the light of node {name} is ignored, since only emissive materials emit light"
+                ));
+            }
+
+            let indent = "  ".repeat(depth(node.index()));
+            match parts.is_empty() {
+                true => report.nodes.push(format!("{indent}{name}")),
+                false => report
+                    .nodes
+                    .push(format!("{indent}{name}: {}", parts.join(", "))),
+            }
+            Ok(())
+        })?;
+    }
+
+    // The last camera in the scene is used unless one is selected with `--camera`
+    match report.cameras.last_mut() {
+        Some(camera) => camera.push_str(", used by default"),
+        None => report
+            .warnings
+            .push(String::from("the scene has no camera")),
+    }
+
+    let mut checked = Materials::new();
+    for material in materials.values() {
+        if let Err(error) = checked.add(material) {
+            report.errors.push(error);
+        }
+    }
+
+    report.triangles = triangles.len();
+    if !triangles.is_empty() {
+        report.bvh = Some(Bvh::new(&primitives, &mut triangles).stats());
+    } else if !report
+        .errors
+        .iter()
+        .any(|error| matches!(error, Error::Primitive { .. }))
+    {
+        let message = String::from("the scene has no triangles");
+        report.errors.push(Error::Scene(message));
+    }
+    Ok(report)
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        section(f, "Nodes", &self.nodes)?;
+        section(f, "Meshes", &self.meshes)?;
+        section(f, "Materials", &self.materials)?;
+        section(f, "Images", &self.images)?;
+        section(f, "Cameras", &self.cameras)?;
+        section(f, "Lights", &self.lights)?;
+        writeln!(
+            f,
+            "Scene: {} primitives, {} triangles",
+            self.primitives, self.triangles
+        )?;
+        if let Some(bvh) = &self.bvh {
+            writeln!(
+                f,
+                "BVH: {} nodes, {} leaves, a depth of {}, at most {} triangles per leaf",
+                bvh.nodes, bvh.leaves, bvh.depth, bvh.max_leaf_triangles
+            )?;
+            writeln!(
+                f,
+                "BVH: a SAH cost of {:.2}, built in {:.1} ms",
+                bvh.sah_cost,
+                bvh.build_time.as_secs_f64() * 1000.0
+            )?;
+        }
+        if !self.warnings.is_empty() {
+            section(f, "Warnings", &self.warnings)?;
+        }
+        if !self.errors.is_empty() {
+            section(f, "Errors", &self.errors)?;
+        }
+        Ok(())
+    }
+}
+
+fn section(f: &mut fmt::Formatter<'_>, title: &str, lines: &[impl Display]) -> fmt::Result {
+    writeln!(f, "{title} ({}):", lines.len())?;
+    for line in lines {
+        writeln!(f, "  {line}")?;
+    }
+    Ok(())
+}
@@ -1,18 +1,27 @@
-use encase::{ShaderType, StorageBuffer, UniformBuffer};
+use encase::{ShaderType, UniformBuffer};
 use glam::{Mat4, Quat, Vec3};
 use gltf::{buffer, camera::Projection, image, scene, Node};
 
 use crate::{
-    config::Config, core::Triangle, materials::Materials, primitives::Primitives,
-    render::RenderContext, textures::Textures,
+    config::GeometryLayout,
+    core::{Triangle, TriangleEdges},
+    error::{describe, Error},
+    materials::Materials,
+    primitives::Primitives,
+    render::{RenderContext, StorageArray, StorageArrayLayout},
+    settings::RenderSettings,
+    textures::Textures,
 };
 
 use self::bvh::Bvh;
 
 mod bvh;
 mod camera;
+mod inspect;
 
+pub use bvh::BvhStats;
 pub use camera::{Camera, CameraBuilder};
+pub use inspect::{inspect, Report};
 
 pub struct Scene<'a> {
     pub primitives: Primitives,
@@ -20,6 +29,8 @@ pub struct Scene<'a> {
     pub textures: Textures<'a>,
     uniform: Uniform,
     triangles: Vec<Triangle>,
+    // The node of the camera rendered from, if the scene has one
+    camera_node: Option<String>,
 }
 
 #[derive(Default, ShaderType)]
@@ -50,6 +61,7 @@ impl<'a> Scene<'a> {
             textures: Textures::new(context),
             uniform: Uniform::default(),
             triangles: Vec::new(),
+            camera_node: None,
         }
     }
 
@@ -58,70 +70,95 @@ impl<'a> Scene<'a> {
         self.uniform.hdri = hdri;
     }
 
+    // Loads the first scene of the document
     pub fn load(
         &mut self,
-        config: &Config,
-        scene: &gltf::Scene,
+        settings: &RenderSettings,
+        document: &gltf::Document,
         buffers: &[buffer::Data],
         images: &[image::Data],
-    ) {
-        for image in images {
-            self.textures.add_texture(image);
+    ) -> Result<(), Error> {
+        for (image, data) in document.images().zip(images) {
+            self.textures.add_texture(&image_name(&image), data)?;
         }
 
+        let scene = document
+            .scenes()
+            .next()
+            .ok_or_else(|| Error::Scene(String::from("the document has no scenes")))?;
+        if let Some(camera) = &settings.camera {
+            if !document.nodes().any(|node| is_camera(&node, camera)) {
+                return Err(Error::Scene(format!(
+                    "the document has no camera node {camera}"
+                )));
+            }
+        }
         for node in scene.nodes() {
-            self.load_node(node, config, buffers, &Mat4::IDENTITY);
+            walk(node, &Mat4::IDENTITY, &mut |node, transform| {
+                self.load_node(node, settings, buffers, transform)
+            })?;
         }
+        Ok(())
     }
 
     fn load_node(
         &mut self,
-        node: Node,
-        config: &Config,
+        node: &Node,
+        settings: &RenderSettings,
         buffers: &[buffer::Data],
-        parent_transform: &Mat4,
-    ) {
-        let transform_matrix = *parent_transform * transform_to_matrix(&node.transform());
-        let transform = Transform::new(transform_matrix);
+        transform_matrix: &Mat4,
+    ) -> Result<(), Error> {
+        let transform = Transform::new(*transform_matrix);
 
         if let Some(mesh) = node.mesh() {
             for primitive in mesh.primitives() {
-                let material_idx = self.materials.add(&primitive.material()).unwrap();
-                self.triangles.append(
-                    &mut self
-                        .primitives
-                        .add(buffers, &primitive, &transform, material_idx)
-                        .unwrap(),
-                );
+                let material_idx = self.materials.add(&primitive.material())?;
+                let mut triangles =
+                    self.primitives
+                        .add(buffers, node, &primitive, &transform, material_idx)?;
+                self.triangles.append(&mut triangles);
             }
         }
 
-        if let Some(camera) = node.camera() {
-            match camera.projection() {
-                Projection::Perspective(perspective) => {
-                    let mut camera_builder = CameraBuilder::new();
-                    camera_builder
-                        .transform(transform_matrix)
-                        .yfov(perspective.yfov());
-                    if let Some(aspect_ratio) = perspective.aspect_ratio() {
-                        camera_builder.aspect_ratio(aspect_ratio);
-                    }
-                    self.uniform.camera =
-                        camera_builder.build(config.size.width, config.size.height);
-                }
-                _ => todo!(),
-            }
+        let selected = settings
+            .camera
+            .as_ref()
+            .is_none_or(|camera| is_camera(node, camera));
+        if let Some(camera) = node.camera().filter(|_| selected) {
+            self.uniform.camera = camera_builder(node, &camera, transform_matrix)?
+                .build(settings.size.width, settings.size.height);
+            self.camera_node = Some(describe(node.name(), node.index()));
         }
+        Ok(())
+    }
 
-        for child in node.children() {
-            self.load_node(child, config, buffers, &transform_matrix);
-        }
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
     }
 
+    pub fn camera_node(&self) -> Option<&str> {
+        self.camera_node.as_deref()
+    }
+
+    // The layouts of the arrays are returned for the shaders, and the statistics of the BVH for
+    // the report of the render
     pub fn build(
         &mut self,
         context: &RenderContext,
-    ) -> encase::internal::Result<(wgpu::BindGroupLayout, wgpu::BindGroup)> {
+        layout: GeometryLayout,
+    ) -> Result<
+        (
+            wgpu::BindGroupLayout,
+            wgpu::BindGroup,
+            Vec<StorageArrayLayout>,
+            BvhStats,
+        ),
+        Error,
+    > {
+        // The BVH needs at least one triangle
+        if self.triangles.is_empty() {
+            return Err(Error::Scene(String::from("the scene has no triangles")));
+        }
         let device = context.device();
         let queue = context.queue();
 
@@ -139,87 +176,120 @@ impl<'a> Scene<'a> {
 
         let bvh = Bvh::new(&self.primitives, &mut self.triangles);
 
-        let mut wgsl_bytes = StorageBuffer::new(Vec::new());
-        wgsl_bytes.write(&self.triangles)?;
-        let wgsl_bytes = wgsl_bytes.into_inner();
-
-        let triangle_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: wgsl_bytes.len() as u64,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
-            mapped_at_creation: false,
-        });
-        queue.write_buffer(&triangle_buffer, 0, &wgsl_bytes);
-
-        let mut wgsl_bytes = StorageBuffer::new(Vec::new());
-        wgsl_bytes.write(&bvh.flatten())?;
-        let wgsl_bytes = wgsl_bytes.into_inner();
-
-        let bvh_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: wgsl_bytes.len() as u64,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
-            mapped_at_creation: false,
-        });
-        queue.write_buffer(&bvh_buffer, 0, &wgsl_bytes);
+        let triangles =
+            StorageArray::try_new(context, "triangle", "triangles", 1, &self.triangles)?;
+        let bvh_nodes = StorageArray::try_new(context, "BVH", "bvh_nodes", 2, &bvh.flatten())?;
+        // The compact layout intersects the triangles with their precomputed edges
+        let edges = match layout {
+            GeometryLayout::Standard => None,
+            GeometryLayout::Compact => {
+                let edges: Vec<_> = self
+                    .triangles
+                    .iter()
+                    .map(|triangle| TriangleEdges::new(triangle, &self.primitives))
+                    .collect();
+                Some(StorageArray::try_new(
+                    context,
+                    "triangle edge",
+                    "triangle_edges",
+                    3,
+                    &edges,
+                )?)
+            }
+        };
+        // The chunks may exceed the bindings of the device
+        context.check_limits(0)?;
 
+        let layout_entries: Vec<_> = std::iter::once(wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        })
+        .chain(triangles.layout_entries())
+        .chain(bvh_nodes.layout_entries())
+        .chain(edges.iter().flat_map(StorageArray::layout_entries))
+        .collect();
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: true },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
+            entries: &layout_entries,
         });
 
+        let entries: Vec<_> = std::iter::once(wgpu::BindGroupEntry {
+            binding: 0,
+            resource: uniform_buffer.as_entire_binding(),
+        })
+        .chain(triangles.bind_group_entries())
+        .chain(bvh_nodes.bind_group_entries())
+        .chain(edges.iter().flat_map(StorageArray::bind_group_entries))
+        .collect();
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: uniform_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: triangle_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: bvh_buffer.as_entire_binding(),
-                },
-            ],
+            entries: &entries,
         });
 
-        Ok((bind_group_layout, bind_group))
+        let layouts = [Some(&triangles), Some(&bvh_nodes), edges.as_ref()]
+            .into_iter()
+            .flatten()
+            .map(StorageArray::layout)
+            .collect();
+        Ok((bind_group_layout, bind_group, layouts, bvh.stats()))
+    }
+}
+
+// Images embedded in buffer views or data URIs are named by their index
+fn image_name(image: &gltf::Image) -> String {
+    match image.source() {
+        image::Source::Uri { uri, .. } if !uri.starts_with("data:") => String::from(uri),
+        _ => describe(image.name(), image.index()),
+    }
+}
+
+// Visits the node and its descendants depth-first with their world transforms
+pub fn walk<'a>(
+    node: Node<'a>,
+    parent_transform: &Mat4,
+    visit: &mut impl FnMut(&Node<'a>, &Mat4) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let transform = *parent_transform * transform_to_matrix(&node.transform());
+    visit(&node, &transform)?;
+    for child in node.children() {
+        walk(child, &transform, visit)?;
     }
+    Ok(())
+}
+
+fn camera_builder(
+    node: &Node,
+    camera: &gltf::Camera,
+    transform: &Mat4,
+) -> Result<CameraBuilder, Error> {
+    match camera.projection() {
+        Projection::Perspective(perspective) => {
+            let mut camera_builder = CameraBuilder::new();
+            camera_builder
+                .transform(*transform)
+                .yfov(perspective.yfov());
+            if let Some(aspect_ratio) = perspective.aspect_ratio() {
+                camera_builder.aspect_ratio(aspect_ratio);
+            }
+            Ok(camera_builder)
+        }
+        Projection::Orthographic(_) => Err(Error::Camera {
+            node: describe(node.name(), node.index()),
+            reason: String::from("is orthographic, which is not supported"),
+        }),
+    }
+}
+
+// Camera nodes are selected by their name or their index
+fn is_camera(node: &Node, camera: &str) -> bool {
+    node.camera().is_some() && (node.name() == Some(camera) || camera.parse() == Ok(node.index()))
 }
 
 fn transform_to_matrix(transform: &scene::Transform) -> Mat4 {
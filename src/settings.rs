@@ -0,0 +1,195 @@
+use clap::{Args, Parser};
+
+use crate::config::{
+    Aov, Backend, BitDepth, ColorSpace, GeometryLayout, Region, RegionOutput, Size, Stage,
+    ToneMapping,
+};
+
+// The settings of a render, which are flattened into the command line but can be constructed on
+// their own when the crate is embedded
+#[derive(Args, Clone)]
+pub struct RenderSettings {
+    /// The image size of the output
+    #[arg(short, long, default_value_t = Size::new(400, 225))]
+    pub size: Size,
+
+    /// The auxiliary passes to be written along with the image, separated by commas
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub aovs: Vec<Aov>,
+
+    /// Denoise the image with an edge-aware filter guided by the AOVs
+    #[arg(long)]
+    pub denoise: bool,
+
+    /// The strength of the denoiser, which scales how much the luminance of neighbors may differ
+    #[arg(long, default_value_t = 1.0)]
+    pub denoise_strength: f32,
+
+    /// The number of iterations of the denoiser, which doubles its radius each time
+    #[arg(long, default_value_t = 5)]
+    pub denoise_iterations: u32,
+
+    /// The name or the index of the camera node to render from, which is the last one if unset
+    #[arg(long)]
+    pub camera: Option<String>,
+
+    /// The maximum depth of each camera ray
+    #[arg(long, default_value_t = 50)]
+    pub depth: u32,
+
+    /// The number of samples per pixel
+    #[arg(long, default_value_t = 1000)]
+    pub samples: u32,
+
+    /// The window of the image to be rendered as WxH+X+Y, in pixels or in fractions of the size
+    #[arg(long)]
+    pub region: Option<Region>,
+
+    /// How the pixels outside of the region are output
+    #[arg(long, value_enum, default_value_t = RegionOutput::Crop)]
+    pub region_output: RegionOutput,
+
+    /// The maximum edge length of the tiles the image is rendered in, which bounds the GPU memory
+    #[arg(long)]
+    pub tile_size: Option<u32>,
+
+    /// The index of the first sample, which keeps distributed renders of a scene disjoint
+    #[arg(long, default_value_t = 0)]
+    pub sample_offset: u32,
+
+    /// The operator mapping the radiance to displayable colors
+    #[arg(long, global = true, value_enum, default_value_t = ToneMapping::Reinhard)]
+    pub tone_mapping: ToneMapping,
+
+    /// The white point of the extended Reinhard operator, which is infinite if unset
+    #[arg(long, global = true)]
+    pub white_point: Option<f32>,
+
+    /// The color space of the output, which is tagged in PNG files
+    #[arg(long, global = true, value_enum, default_value_t = ColorSpace::Srgb)]
+    pub color_space: ColorSpace,
+
+    /// The exposure in EV stops, which compensates the metered exposure with `--auto-exposure`
+    #[arg(long, global = true, allow_hyphen_values = true, default_value_t = 0.0)]
+    pub exposure: f32,
+
+    /// Meter the exposure from a luminance histogram of the rendered image
+    #[arg(long, global = true)]
+    pub auto_exposure: bool,
+
+    /// The percentile of the luminance exposed to middle gray by `--auto-exposure`
+    #[arg(long, global = true, default_value_t = 50.0)]
+    pub exposure_percentile: f32,
+
+    /// Add a bloom of the radiance above the threshold before tone mapping
+    #[arg(long, global = true)]
+    pub bloom: bool,
+
+    /// The radiance above which pixels bloom, before the exposure is applied
+    #[arg(long, global = true, default_value_t = 1.0)]
+    pub bloom_threshold: f32,
+
+    /// The fraction of the radiance above the threshold spread into the bloom
+    #[arg(long, global = true, default_value_t = 0.1)]
+    pub bloom_intensity: f32,
+
+    /// The radius of the bloom in pixels, which is rounded up to a power of two
+    #[arg(long, global = true, default_value_t = 64)]
+    pub bloom_radius: u32,
+
+    /// The number of aperture blades of the star-shaped glare added to the bloom
+    #[arg(long, global = true, requires = "bloom", value_parser = clap::value_parser!(u32).range(1..))]
+    pub glare_blades: Option<u32>,
+
+    /// A 3D LUT in the .cube format for color grading, which is applied after tone mapping
+    #[arg(long, global = true)]
+    pub lut: Option<String>,
+
+    /// The strength of the vignetting, which darkens the corners to 1 / (1 + strength)² of the center
+    #[arg(long, global = true, default_value_t = 0.0)]
+    pub vignette: f32,
+
+    /// Dither the output to hide banding in smooth gradients
+    #[arg(long, global = true)]
+    pub dither: bool,
+
+    /// The order of the post-processing stages, of which those not enabled are skipped
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "exposure,denoise,bloom,tonemap,lut,vignette,dither"
+    )]
+    pub post_stages: Vec<Stage>,
+
+    /// The environment map of the scene to be rendered
+    #[arg(long, default_value_t = String::from("textures/indoor.hdr"))]
+    pub hdri: String,
+
+    /// The path to periodically save the accumulated samples to, which is not supported for tiled
+    /// renders
+    #[arg(long)]
+    pub checkpoint: Option<String>,
+
+    /// The number of samples taken between two checkpoints
+    #[arg(long, default_value_t = 100)]
+    pub checkpoint_interval: u32,
+
+    /// Resume rendering from the checkpoint if it exists
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+
+    /// The graphics API to render with, which is chosen from all available ones if unset
+    #[arg(long, global = true, value_enum)]
+    pub backend: Option<Backend>,
+
+    /// Render on the first adapter whose name contains this text, ignoring the case
+    #[arg(long, global = true, conflicts_with = "fallback_adapter")]
+    pub adapter: Option<String>,
+
+    /// Render on the software adapter of the backend, e.g. on machines without a GPU
+    #[arg(long, global = true)]
+    pub fallback_adapter: bool,
+
+    /// Pack the textures into an atlas even if the adapter can bind arrays of textures
+    #[arg(long, global = true)]
+    pub texture_atlas: bool,
+
+    /// The layout of the vertices on the device
+    #[arg(long, global = true, value_enum, default_value_t = GeometryLayout::Standard)]
+    pub geometry_layout: GeometryLayout,
+
+    /// The bits per channel of the post-processed image
+    #[arg(skip = BitDepth::Eight)]
+    pub bit_depth: BitDepth,
+
+    /// Additionally post-process the image without denoising
+    #[arg(skip)]
+    pub keep_noisy: bool,
+
+    /// Count the rays traced and time each sample, which waits for the samples one by one
+    #[arg(skip)]
+    pub collect_stats: bool,
+}
+
+impl RenderSettings {
+    // The denoiser is guided by the AOVs, which are rendered for it even if none is output
+    pub fn renders_aovs(&self) -> bool {
+        !self.aovs.is_empty() || self.denoise
+    }
+}
+
+// The defaults of the command line, which are parsed from an empty one so that they can't drift
+// apart from the arguments
+impl Default for RenderSettings {
+    fn default() -> Self {
+        #[derive(Parser)]
+        struct Defaults {
+            #[command(flatten)]
+            settings: RenderSettings,
+        }
+
+        Defaults::parse_from([env!("CARGO_PKG_NAME")]).settings
+    }
+}
@@ -0,0 +1,237 @@
+use encase::{ShaderType, StorageBuffer};
+use glam::UVec2;
+
+use crate::{error::Error, render::RenderContext};
+
+use super::TextureTrait;
+
+// The texels of a texture in the atlas
+#[derive(Clone, Copy, Default, ShaderType)]
+struct AtlasRect {
+    origin: UVec2,
+    size: UVec2,
+    layer: u32,
+}
+
+pub struct Atlas {
+    pub texture: wgpu::Texture,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl Atlas {
+    // Copies the textures of the materials into the layers of an array texture, and binds the
+    // environment map on its own since it has a different format
+    pub fn try_new(
+        context: &RenderContext,
+        registry: &[Box<dyn TextureTrait>],
+    ) -> Result<Self, Error> {
+        let device = context.device();
+        let queue = context.queue();
+
+        let panorama = registry
+            .iter()
+            .map(|texture| texture.texture())
+            .find(|texture| is_panorama(texture))
+            .ok_or_else(|| Error::Scene(String::from("the environment map is missing")))?;
+
+        let sizes: Vec<_> = registry
+            .iter()
+            .map(|texture| texture.texture())
+            .filter(|texture| !is_panorama(texture))
+            .map(|texture| UVec2::new(texture.width(), texture.height()))
+            .collect();
+        let limits = device.limits();
+        let (layer_size, mut rects) = pack(&sizes, limits.max_texture_dimension_2d)?;
+        let mut layers = rects.iter().map(|rect| rect.layer + 1).max().unwrap_or(1);
+        // The GL backend guesses the view dimension from the layers, which would make a single
+        // layer a 2D texture
+        if context.adapter_info().backend == wgpu::Backend::Gl {
+            layers = layers.max(2);
+        }
+        if layers > limits.max_texture_array_layers {
+            return Err(Error::Texture {
+                image: String::from("atlas"),
+                reason: format!(
+                    "needs {layers} layers, but the device supports {}",
+                    limits.max_texture_array_layers
+                ),
+            });
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width: layer_size,
+                height: layer_size,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        let textures = registry
+            .iter()
+            .map(|texture| texture.texture())
+            .filter(|texture| !is_panorama(texture));
+        for (source, rect) in textures.zip(&rects) {
+            encoder.copy_texture_to_texture(
+                wgpu::ImageCopyTexture {
+                    texture: source,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: rect.origin.x,
+                        y: rect.origin.y,
+                        z: rect.layer,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                source.size(),
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+
+        // The rectangles are indexed like the textures, for which the environment map has an
+        // unused one
+        for (i, texture) in registry.iter().enumerate() {
+            if is_panorama(texture.texture()) {
+                rects.insert(i, AtlasRect::default());
+            }
+        }
+        let mut wgsl_bytes = StorageBuffer::new(Vec::new());
+        wgsl_bytes.write(&rects)?;
+        let wgsl_bytes = wgsl_bytes.into_inner();
+        context.check_buffer_size("texture rectangle", wgsl_bytes.len() as u64)?;
+
+        let rect_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: wgsl_bytes.len() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&rect_buffer, 0, &wgsl_bytes);
+
+        let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                texture_entry(0, wgpu::TextureViewDimension::D2Array),
+                texture_entry(1, wgpu::TextureViewDimension::D2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        // A single layer would be viewed as a 2D texture by default
+        let atlas_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let panorama_view = panorama.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&atlas_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&panorama_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: rect_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(Self {
+            texture,
+            bind_group_layout,
+            bind_group,
+        })
+    }
+}
+
+// The environment map is the only texture of floats
+pub fn is_panorama(texture: &wgpu::Texture) -> bool {
+    texture.format() == wgpu::TextureFormat::Rgba32Float
+}
+
+// Places the textures on shelves of the layers, from the tallest to the shortest. The layers are
+// just large enough for all textures to fit into one if the limit allows it.
+fn pack(sizes: &[UVec2], limit: u32) -> Result<(u32, Vec<AtlasRect>), Error> {
+    let area: u64 = sizes.iter().map(|size| size.x as u64 * size.y as u64).sum();
+    let largest = sizes
+        .iter()
+        .map(|size| size.max_element())
+        .max()
+        .unwrap_or(1);
+    if largest > limit {
+        return Err(Error::Texture {
+            image: String::from("atlas"),
+            reason: format!(
+                "can't hold a texture of {largest} texels, which exceeds the limit of {limit}"
+            ),
+        });
+    }
+    let layer_size = ((area as f64).sqrt().ceil() as u32)
+        .next_power_of_two()
+        .max(largest)
+        .min(limit);
+
+    let mut order: Vec<_> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].y));
+
+    let mut rects = vec![AtlasRect::default(); sizes.len()];
+    let (mut layer, mut x, mut y, mut shelf_height) = (0, 0, 0, 0);
+    for i in order {
+        let size = sizes[i];
+        if x + size.x > layer_size {
+            (x, y) = (0, y + shelf_height);
+            shelf_height = 0;
+        }
+        if y + size.y > layer_size {
+            (layer, x, y) = (layer + 1, 0, 0);
+            shelf_height = 0;
+        }
+        rects[i] = AtlasRect {
+            origin: UVec2::new(x, y),
+            size,
+            layer,
+        };
+        x += size.x;
+        shelf_height = shelf_height.max(size.y);
+    }
+    Ok((layer_size, rects))
+}
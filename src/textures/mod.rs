@@ -1,18 +1,21 @@
-use std::{error::Error, num::NonZeroU32};
+use std::num::NonZeroU32;
 
 use gltf::image::Data;
 use image::ImageReader;
 
-use crate::render::RenderContext;
+use crate::{error::Error, render::RenderContext, settings::RenderSettings};
 
+mod atlas;
 mod texture;
 mod texture_hdr;
 
-use self::{texture::Texture, texture_hdr::TextureHdr};
+use self::{atlas::Atlas, texture::Texture, texture_hdr::TextureHdr};
 
 pub struct Textures<'a> {
     context: &'a RenderContext,
     registry: Vec<Box<dyn TextureTrait>>,
+    // The textures of the materials are dropped once they are copied into the atlas
+    atlas: Option<wgpu::Texture>,
 }
 
 // At lease, use `Trait` instead of just a `2`
@@ -27,11 +30,16 @@ impl<'a> Textures<'a> {
         Self {
             context,
             registry: Vec::new(),
+            atlas: None,
         }
     }
 
-    pub fn load_texture_hdr(&mut self, path: &str) -> Result<u32, Box<dyn Error>> {
-        let image = ImageReader::open(path)?.decode()?.into_rgba32f();
+    pub fn load_texture_hdr(&mut self, path: &str) -> Result<u32, Error> {
+        let image = ImageReader::open(path)
+            .map_err(Error::io(path))?
+            .decode()
+            .map_err(Error::image(path))?
+            .into_rgba32f();
         let width = image.width();
         let height = image.height();
         self.registry.push(Box::new(TextureHdr::try_new(
@@ -43,32 +51,49 @@ impl<'a> Textures<'a> {
         Ok(self.registry.len() as u32 - 1)
     }
 
-    pub fn add_texture(&mut self, image: &Data) -> u32 {
-        let num_pixels = (image.width * image.height) as usize;
-        let mut data = Vec::with_capacity(num_pixels * 4);
-
-        match image.format {
-            gltf::image::Format::R8G8B8 => {
-                for i in 0..num_pixels {
-                    data.push(image.pixels[3 * i]);
-                    data.push(image.pixels[3 * i + 1]);
-                    data.push(image.pixels[3 * i + 2]);
-                    data.push(0xFF);
-                }
-            }
-            _ => todo!(),
-        }
-
+    // The image is named by its URI or index for errors
+    pub fn add_texture(&mut self, name: &str, image: &Data) -> Result<u32, Error> {
+        let data = to_rgba8(name, image)?;
         self.registry.push(Box::new(Texture::new(
             self.context,
             &data,
             image.width,
             image.height,
         )));
-        self.registry.len() as u32 - 1
+        Ok(self.registry.len() as u32 - 1)
     }
 
-    pub fn build(&self) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+    // The bytes of all textures on the device, including the environment map
+    pub fn memory(&self) -> u64 {
+        self.registry
+            .iter()
+            .map(|texture| texture.texture())
+            .chain(&self.atlas)
+            .map(|texture| {
+                let texel_size = texture.format().block_copy_size(None).unwrap_or(0);
+                let size = texture.size();
+                let texels = size.width as u64 * size.height as u64;
+                texels * size.depth_or_array_layers as u64 * texel_size as u64
+            })
+            .sum()
+    }
+
+    pub fn build(
+        &mut self,
+        atlas: bool,
+    ) -> Result<(wgpu::BindGroupLayout, wgpu::BindGroup), Error> {
+        if atlas {
+            let Atlas {
+                texture,
+                bind_group_layout,
+                bind_group,
+            } = Atlas::try_new(self.context, &self.registry)?;
+            self.registry
+                .retain(|texture| atlas::is_panorama(texture.texture()));
+            self.atlas = Some(texture);
+            return Ok((bind_group_layout, bind_group));
+        }
+
         let device = self.context.device();
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -85,9 +110,8 @@ impl<'a> Textures<'a> {
             }],
         });
 
-        let view_array: Vec<&wgpu::TextureView> = self.registry.iter()
-            .map(|texture| texture.view())
-            .collect();
+        let view_array: Vec<&wgpu::TextureView> =
+            self.registry.iter().map(|texture| texture.view()).collect();
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: None,
             layout: &bind_group_layout,
@@ -97,6 +121,35 @@ impl<'a> Textures<'a> {
             }],
         });
 
-        (bind_group_layout, bind_group)
+        Ok((bind_group_layout, bind_group))
+    }
+}
+
+// The textures are packed into an atlas if the adapter can't index an array of them
+pub fn uses_atlas(context: &RenderContext, settings: &RenderSettings) -> bool {
+    settings.texture_atlas || !context.has_binding_arrays()
+}
+
+// Only RGB images are supported, which are padded with an opaque alpha
+pub fn to_rgba8(name: &str, image: &Data) -> Result<Vec<u8>, Error> {
+    let num_pixels = (image.width * image.height) as usize;
+    let mut data = Vec::with_capacity(num_pixels * 4);
+
+    match image.format {
+        gltf::image::Format::R8G8B8 => {
+            for i in 0..num_pixels {
+                data.push(image.pixels[3 * i]);
+                data.push(image.pixels[3 * i + 1]);
+                data.push(image.pixels[3 * i + 2]);
+                data.push(0xFF);
+            }
+        }
+        format => {
+            return Err(Error::Texture {
+                image: name.to_string(),
+                reason: format!("has the unsupported format {format:?}"),
+            });
+        }
     }
+    Ok(data)
 }
@@ -1,6 +1,6 @@
-use std::{error::Error, slice};
+use std::slice;
 
-use crate::render::RenderContext;
+use crate::{error::Error, render::RenderContext};
 
 use super::TextureTrait;
 
@@ -15,7 +15,7 @@ impl TextureHdr {
         data: &[f32],
         width: u32,
         height: u32,
-    ) -> Result<Self, Box<dyn Error>> {
+    ) -> Result<Self, Error> {
         let device = context.device();
         let queue = context.queue();
 